@@ -18,6 +18,10 @@ pub struct AtlasTexture {
     pub height: u32,
     #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
     pub extra: Option<AtlasTextureExtra>,
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub trim: Option<AtlasTextureTrim>,
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub flip: Option<AtlasTextureFlip>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,16 +31,41 @@ pub struct AtlasTextureExtra {
     pub rotated: bool,
 }
 
+/// Where a trimmed texture sits in its original, untrimmed image
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AtlasTextureTrim {
+    pub offset_x: u32,
+    pub offset_y: u32,
+    pub source_width: u32,
+    pub source_height: u32,
+}
+
+/// How a duplicate texture is flipped from the one that was packed (see `FlipData`)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AtlasTextureFlip {
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub flip_d: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AtlasPage {
     pub texture: String,
     pub width: u32,
     pub height: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings: Option<String>,
+    //whether the colors of the pixels are multiplied by their alpha
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub premultiplied: bool,
     pub regions: Vec<AtlasTexture>,
 }
 
+/// The name of a page image, whether its pixels are premultiplied, and its textures
+pub type DescribedPage = (String, bool, Vec<SourceTexture>);
+
 pub fn generate_description(
-    args: &interface::PackArguments, packer: &TexturePacker,
+    args: &interface::PackArguments, packer: &TexturePacker, settings: Option<String>,
 ) -> Option<String> {
     let mut r: Vec<AtlasPage> = Vec::new();
     for (idx, page) in packer.pages.iter().enumerate() {
@@ -44,15 +73,14 @@ pub fn generate_description(
             1 => format!("{}.png", packer.label),
             _ => format!("{}-{}.png", packer.label, idx),
         };
-        let (width, height) = match page.size {
-            Some((w, h)) => (w, h),
-            None => page.packed_bounds(),
-        };
+        let (width, height) = page.dimensions();
         let regions: Vec<AtlasTexture> = page.textures.iter().map(AtlasTexture::from).collect();
         r.push(AtlasPage {
             texture,
             width,
             height,
+            settings: settings.clone(),
+            premultiplied: args.premultiply,
             regions,
         });
     }
@@ -67,7 +95,7 @@ pub fn generate_description(
     formatter.format_atlas(&r)
 }
 
-pub fn read_from_description(source: &str) -> Option<Vec<(String, Vec<SourceTexture>)>> {
+pub fn read_from_description(source: &str) -> Option<Vec<DescribedPage>> {
     let formats = [OutputFormat::Json, OutputFormat::Text];
     for fmt in formats {
         let formatter = create_formatter(&Some(fmt));
@@ -89,7 +117,7 @@ fn create_formatter(format: &Option<interface::OutputFormat>) -> Box<dyn AtlasFo
 impl<T: Borrow<AtlasTexture>> From<T> for SourceTexture {
     fn from(src: T) -> Self {
         use crate::rectangle::Rect;
-        use crate::sources::PackingData;
+        use crate::sources::{FlipData, PackingData, TrimData};
         let src = src.borrow();
         let name_without_slashes = src.name.to_owned().replace('/', "-");
         let pd = PackingData {
@@ -99,33 +127,46 @@ impl<T: Borrow<AtlasTexture>> From<T> for SourceTexture {
                 width: src.width,
                 height: src.height,
             },
-            rotated: match &src.extra {
-                Some(extra) => {
-                    extra.original_width != src.width || extra.original_height != src.height
-                }
-                None => false,
-            },
+            rotated: src.extra.as_ref().is_some_and(|x| x.rotated),
+        };
+        let flip = src.flip.as_ref().map(|x| FlipData {
+            horizontal: x.flip_h,
+            vertical: x.flip_v,
+            diagonal: x.flip_d,
+        });
+        //both rotating and flipping diagonally swap the texture's dimensions
+        let diagonal = flip.is_some_and(|x| x.diagonal);
+        let dimensions = if pd.rotated != diagonal {
+            Rect {
+                x: 0,
+                y: 0,
+                width: src.height,
+                height: src.width,
+            }
+        } else {
+            Rect {
+                x: 0,
+                y: 0,
+                width: src.width,
+                height: src.height,
+            }
         };
         SourceTexture {
             name: name_without_slashes.clone(),
             path: std::path::PathBuf::from(&name_without_slashes),
-            dimensions: if pd.rotated {
-                Rect {
-                    x: 0,
-                    y: 0,
-                    width: src.height,
-                    height: src.width,
-                }
-            } else {
-                Rect {
-                    x: 0,
-                    y: 0,
-                    width: src.width,
-                    height: src.height,
-                }
-            },
+            trim: src.trim.as_ref().map(|x| TrimData {
+                rect: Rect {
+                    x: x.offset_x,
+                    y: x.offset_y,
+                    width: dimensions.width,
+                    height: dimensions.height,
+                },
+                source_size: (x.source_width, x.source_height),
+            }),
+            dimensions,
             replica_of: None,
             packing: Some(pd),
+            flip,
         }
     }
 }
@@ -145,6 +186,17 @@ impl<T: Borrow<SourceTexture>> From<T> for AtlasTexture {
                 original_height: src.dimensions.height,
                 rotated: packing.rotated,
             }),
+            trim: src.trim.as_ref().map(|x| AtlasTextureTrim {
+                offset_x: x.rect.x,
+                offset_y: x.rect.y,
+                source_width: x.source_size.0,
+                source_height: x.source_size.1,
+            }),
+            flip: src.flip.as_ref().map(|x| AtlasTextureFlip {
+                flip_h: x.horizontal,
+                flip_v: x.vertical,
+                flip_d: x.diagonal,
+            }),
         }
     }
 }
@@ -2,7 +2,7 @@ use std::{io::Write, path::PathBuf};
 
 use super::pack;
 use crate::{
-    interface, outputs,
+    images, interface, outputs,
     packing::{self, TexturePacker},
     utils,
 };
@@ -21,5 +21,9 @@ pub fn arrange(
     pack::print_packing_report(&packer, log);
     let destination =
         outputs::prepare_output_directory(&args.output, outputs::PathType::Files, log)?;
-    pack::generate_image_files(destination, packer, args.overwrite, log)
+    let filters = images::PageFilters {
+        bleed: args.bleed,
+        premultiply: args.premultiply,
+    };
+    pack::generate_image_files(destination, packer, args.overwrite, filters, log)
 }
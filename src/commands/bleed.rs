@@ -0,0 +1,31 @@
+use std::{io::Write, path::PathBuf};
+
+use crate::{images, interface, outputs, utils};
+
+pub fn bleed(
+    args: &interface::BleedArguments, log: &mut Option<impl Write>,
+) -> utils::GeneralResult<()> {
+    let source_path = {
+        let s = PathBuf::from(&args.source);
+        if s.is_relative() {
+            std::env::current_dir()?.join(s)
+        } else {
+            s
+        }
+    };
+    if !source_path.is_file() {
+        return Err(format!("file '{}' not found.", source_path.display()).into());
+    }
+    let output_path = {
+        let name = match PathBuf::from(&args.output).file_name() {
+            Some(name) => name.to_os_string(),
+            None => return Err(format!("unable to extract filename from '{}'", args.output).into()),
+        };
+        let dir = outputs::prepare_output_directory(&args.output, outputs::PathType::Files, log)?;
+        dir.join(name)
+    };
+    if let Some(msg) = outputs::notify_overwrite(&output_path, args.overwrite)? {
+        utils::info_message(log, msg);
+    }
+    images::bleed_image((&source_path, &output_path))
+}
@@ -43,10 +43,10 @@ pub fn lut(
         Some(i) => Some(palette_from_image(i)?),
         None => None,
     };
-    if args.expand {
-        if let Some(p) = palette.take() {
-            palette.replace(expand_palette(&p)?);
-        }
+    if args.expand
+        && let Some(p) = palette.take()
+    {
+        palette.replace(expand_palette(&p)?);
     }
     let (color_count, pixels) = generate_lut_pixels(settings.dimensions, palette);
     let output_path = {
@@ -1,11 +1,15 @@
 mod arrange;
+mod bleed;
 mod lut;
 mod pack;
 mod query;
+mod tileset;
 mod unpack;
 
 pub use arrange::arrange;
+pub use bleed::bleed;
 pub use lut::lut;
 pub use pack::pack;
 pub use query::query;
+pub use tileset::tileset;
 pub use unpack::unpack;
@@ -1,6 +1,7 @@
 use std::{
     cmp,
-    fs::File,
+    collections::HashMap,
+    fs::{self, File},
     io::Write,
     path::{Path, PathBuf},
     sync::mpsc,
@@ -9,18 +10,79 @@ use std::{
 
 use indicatif::ProgressBar;
 use packing::TexturePacker;
+use serde::Deserialize;
 use utils::info_message;
 
-use crate::{atlas, images, interface, outputs, packing, sources, utils};
+use super::query;
+use crate::{atlas, images, interface, outputs, packing, rectangle::Rect, sources, utils};
 
 pub const EXTENSIONS: [&str; 1] = ["png"];
 
+/// The position a texture must be packed at, in page coordinates
+#[derive(Debug, Deserialize)]
+struct Pin {
+    name: String,
+    x: u32,
+    y: u32,
+    #[serde(default)]
+    page: usize,
+}
+
+/// Where each texture is going, by name, along with the index of its page
+type Positions = HashMap<String, (usize, sources::PackingData)>;
+
 pub fn pack(
     args: &interface::PackArguments, log: &mut Option<impl Write>,
 ) -> utils::GeneralResult<()> {
-    let packer = pack_textures(args, log)?;
+    let (args, packer) = match args.auto {
+        true => pack_automatically(args, log)?,
+        false => (args.clone(), pack_textures(args, log)?),
+    };
     print_packing_report(&packer, log);
-    generate_output_files(args, packer, log)
+    generate_output_files(&args, packer, log)
+}
+
+/// Packs the sources with every candidate from the query, and keeps
+/// the best result along with the arguments that produced it
+fn pack_automatically(
+    args: &interface::PackArguments, log: &mut Option<impl Write>,
+) -> utils::GeneralResult<(interface::PackArguments, TexturePacker)> {
+    let packing_settings = packing::generate_packing_settings(args)?;
+    let sources = prepare_sources(&args.sources, &EXTENSIONS, &packing_settings)?;
+    validate_sources(&sources, &packing_settings)?;
+    if let Some((_, msg)) = sources::report_duplicates(&sources) {
+        info_message(log, msg);
+    }
+    let label = output_label(args)?;
+    //rotation is only tried when it's allowed
+    let candidates = query::generate_candidates(args)
+        .into_iter()
+        .filter(|x| args.rotate || !x.rotate)
+        .collect();
+    let mut results = query::pack_candidates(candidates, &sources, &label, log)?;
+    query::rank_results(&mut results);
+    match results.into_iter().next() {
+        Some((args, packer, _, _)) => {
+            info_message(
+                log,
+                format!(
+                    "picked {}, same as 'atlas pack {}[sources] [output]'",
+                    query::describe_settings(&args),
+                    query::describe_args(&args)
+                ),
+            );
+            Ok((args, packer))
+        }
+        None => Err("no packing candidates to choose from.".into()),
+    }
+}
+
+fn output_label(args: &interface::PackArguments) -> utils::GeneralResult<String> {
+    //extract the filename stem from the output command line argument
+    match PathBuf::from(&args.output).file_stem() {
+        Some(stem) => Ok(stem.to_string_lossy().to_string()),
+        None => Err(format!("unable to extract filename from '{}'.", args.output).into()),
+    }
 }
 
 pub fn pack_textures(
@@ -29,41 +91,215 @@ pub fn pack_textures(
     //let source_settings = sources::generate_settings(args);
     let packing_settings = packing::generate_packing_settings(args)?;
     let sources = prepare_sources(&args.sources, &EXTENSIONS, &packing_settings)?;
-    //check if page size is large enough to fit all the images
-    if let Some(page_size) = packing_settings.page_size {
-        sources::validate_dimensions(&sources, page_size, packing_settings.spacing)?;
-    }
+    validate_sources(&sources, &packing_settings)?;
     //if there are duplicate images among the sources, list them
     if let Some((_, msg)) = sources::report_duplicates(&sources) {
         info_message(log, msg);
     }
-    //extract the filename stem from the output command line argument
-    let label = match PathBuf::from(&args.output).file_stem() {
-        Some(stem) => stem.to_string_lossy().to_string(),
-        None => return Err(format!("unable to extract filename from '{}'.", args.output).into()),
-    };
-    let packer = TexturePacker::new(&label, sources, packing_settings);
+    let label = output_label(args)?;
+    let pins = pinned_positions(&read_pins(args)?, &sources, &packing_settings)?;
+    if let Some(previous) = args.previous.as_ref() {
+        return pack_incrementally(previous, &label, sources, packing_settings, &pins, log);
+    }
+    let mut packer = TexturePacker::new(&label, sources, packing_settings);
+    pin_textures(&mut packer, &pins)?;
     //perform the rectangle packing on a separate thread, return the packer on sucess
     pack_with_progress_bar(packer, log)
 }
 
+/// Packs the sources around the positions they had in a previous description,
+/// or from scratch if the new ones don't fit in the same number of pages
+fn pack_incrementally(
+    previous: &str, label: &str, sources: Vec<sources::SourceTexture>,
+    settings: packing::PackingSettings, pins: &Positions, log: &mut Option<impl Write>,
+) -> utils::GeneralResult<TexturePacker> {
+    let description = fs::read_to_string(previous)?;
+    let pages = match atlas::read_from_description(&description) {
+        Some(x) => x,
+        None => return Err(format!("failed to parse description file '{previous}'.").into()),
+    };
+    let page_count = pages.len();
+    //flipped textures were duplicates, they didn't take up any space of their own
+    let positions: Positions = pages
+        .into_iter()
+        .enumerate()
+        .flat_map(|(idx, page)| {
+            page.2
+                .into_iter()
+                .filter(|x| x.flip.is_none())
+                .filter_map(move |x| x.packing.map(|p| (x.name, (idx, p))))
+        })
+        .collect();
+    //the descriptions have slashes in the names replaced
+    let positions = sources
+        .iter()
+        .filter_map(|x| {
+            let previous = positions.get(&x.name.replace('/', "-"))?;
+            Some((x.name.clone(), previous.clone()))
+        })
+        .collect();
+    //pinned textures go first, wherever they were before
+    let mut packer = TexturePacker::new(label, sources.clone(), settings.clone());
+    pin_textures(&mut packer, pins)?;
+    let kept = packer.keep_positions(&positions);
+    info_message(
+        log,
+        format!("kept {kept} textures in the same place as in '{previous}'."),
+    );
+    let packer = pack_with_progress_bar(packer, log)?;
+    if packer.pages.len() <= page_count {
+        return Ok(packer);
+    }
+    info_message(
+        log,
+        "the new textures don't fit in the previous pages, packing everything again.",
+    );
+    let mut packer = TexturePacker::new(label, sources, settings);
+    pin_textures(&mut packer, pins)?;
+    pack_with_progress_bar(packer, log)
+}
+
+/// Reads the pinned textures from the pins file, and then from the command line
+fn read_pins(args: &interface::PackArguments) -> utils::GeneralResult<Vec<Pin>> {
+    let mut pins = Vec::new();
+    if let Some(path) = args.pins.as_ref() {
+        let text = fs::read_to_string(path)?;
+        match serde_json::from_str::<Vec<Pin>>(&text) {
+            Ok(x) => pins.extend(x),
+            Err(e) => return Err(format!("failed to read pins from '{path}': {e}.").into()),
+        }
+    }
+    for val in args.pin.iter() {
+        match parse_pin(val) {
+            Some(pin) => pins.push(pin),
+            None => {
+                return Err(format!(
+                    "failed to read pin from '{val}', the format is NAME=X,Y or NAME=X,Y,PAGE."
+                )
+                .into());
+            }
+        }
+    }
+    Ok(pins)
+}
+
+fn parse_pin(val: &str) -> Option<Pin> {
+    let (name, position) = val.rsplit_once('=')?;
+    let values = position
+        .split(',')
+        .map(|x| x.trim().parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    let (x, y, page) = match values[..] {
+        [x, y] => (x, y, 0),
+        [x, y, page] => (x, y, page as usize),
+        _ => return None,
+    };
+    Some(Pin {
+        name: String::from(name),
+        x,
+        y,
+        page,
+    })
+}
+
+/// The positions of the pinned textures, by the name of the texture that
+/// is actually packed, since duplicates are only packed once
+fn pinned_positions(
+    pins: &[Pin], sources: &[sources::SourceTexture], settings: &packing::PackingSettings,
+) -> utils::GeneralResult<Positions> {
+    let mut positions = Positions::new();
+    for pin in pins.iter() {
+        //a single dynamic page grows to fit everything, there are never others
+        if pin.page > 0 && settings.page_size.is_none() && settings.max_size.is_none() {
+            return Err(format!(
+                "unable to pin '{}' to page {}, there is only one page without -p or --max-size.",
+                pin.name, pin.page
+            )
+            .into());
+        }
+        let find = |name: &str| sources.iter().find(|x| x.name == name);
+        let Some(src) = find(&pin.name) else {
+            return Err(format!("there is no source named '{}' to pin.", pin.name).into());
+        };
+        let original = src.replica_of.as_deref().and_then(find).unwrap_or(src);
+        let packing = sources::PackingData {
+            position: Rect::new(
+                pin.x,
+                pin.y,
+                original.dimensions.width,
+                original.dimensions.height,
+            ),
+            rotated: false,
+        };
+        if let Some((page, previous)) =
+            positions.insert(original.name.clone(), (pin.page, packing.clone()))
+            && (page, previous.position) != (pin.page, packing.position)
+        {
+            return Err(format!(
+                "'{}' is pinned in two different places, or is a duplicate of another pinned texture (see --no-dedup).",
+                pin.name
+            )
+            .into());
+        }
+    }
+    Ok(positions)
+}
+
+/// Puts the pinned textures in place before anything else is packed
+fn pin_textures(packer: &mut TexturePacker, pins: &Positions) -> utils::GeneralResult<()> {
+    if pins.is_empty() {
+        return Ok(());
+    }
+    packer.keep_positions(pins);
+    for (name, (page, packing)) in pins.iter() {
+        let pinned = packer
+            .pages
+            .get(*page)
+            .is_some_and(|x| x.textures.iter().any(|x| &x.name == name));
+        if !pinned {
+            let (x, y) = (packing.position.x, packing.position.y);
+            return Err(format!(
+                "unable to pin '{name}' at {x},{y} of page {page}, it doesn't fit there or the space is taken."
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_sources(
+    sources: &[sources::SourceTexture], settings: &packing::PackingSettings,
+) -> utils::GeneralResult<()> {
+    //check if page size is large enough to fit all the images
+    match settings.page_size.or(settings.max_size) {
+        Some(page_size) => {
+            sources::validate_dimensions(sources, page_size, |x| settings.minimum_page_size(x))
+        }
+        None => Ok(()),
+    }
+}
+
+pub fn run_packer(
+    packer: &mut TexturePacker, progress: Option<mpsc::Sender<u64>>,
+) -> utils::GeneralResult<()> {
+    match (&packer.settings.arrange, &packer.settings.search) {
+        (Some(_), _) => packer.arrange_everything(progress),
+        (None, Some(_)) => packer.search_everything(progress),
+        (None, None) => packer.pack_everything(progress),
+    }
+}
+
 pub fn pack_with_progress_bar(
     mut packer: TexturePacker, log: &mut Option<impl Write>,
 ) -> utils::GeneralResult<TexturePacker> {
-    let (sources, duplicates) = (packer.count(), packer.duplicates());
+    let workload = packer.workload();
     let (send, recv) = mpsc::channel::<u64>();
-    let handle = thread::spawn(move || {
-        let r = match packer.settings.arrange {
-            Some(_) => packer.arrange_everything(Some(send)),
-            None => packer.pack_everything(Some(send)),
-        };
-        match r {
-            Ok(_) => Ok(packer),
-            Err(msg) => Err(msg),
-        }
+    let handle = thread::spawn(move || match run_packer(&mut packer, Some(send)) {
+        Ok(_) => Ok(packer),
+        Err(msg) => Err(msg),
     });
     if log.is_some() {
-        let bar = ProgressBar::new(sources.saturating_sub(duplicates) as u64);
+        let bar = ProgressBar::new(workload);
         while let Ok(p) = recv.recv() {
             bar.set_position(bar.position() + p);
         }
@@ -78,21 +314,41 @@ pub fn pack_with_progress_bar(
 pub fn print_packing_report(packer: &TexturePacker, log: &mut Option<impl Write>) {
     let page_size = packer.page_size();
     let page_count = packer.pages.len();
+    //dynamic pages with a maximum size can have different sizes
+    let largest = packer
+        .pages
+        .iter()
+        .map(|x| x.dimensions())
+        .fold(page_size, |acc, x| {
+            (cmp::max(acc.0, x.0), cmp::max(acc.1, x.1))
+        });
+    let uniform = packer.pages.iter().all(|x| x.dimensions() == page_size);
     //report the data
     info_message(
         log,
         format!(
-            "generated {} page{}, size {}x{}.",
+            "generated {} page{}, size {}{}x{}.",
             page_count,
             if page_count == 1 { "" } else { "s" },
-            page_size.0,
-            page_size.1
+            if uniform { "" } else { "up to " },
+            largest.0,
+            largest.1
         ),
     );
     info_message(
         log,
         format!("packing efficiency: {:.2}%.", packer.efficiency()).as_str(),
     );
+    //pins and previous positions can leave pages with nothing in them
+    let empty: Vec<&str> = packer
+        .pages
+        .iter()
+        .filter(|x| x.textures.is_empty())
+        .map(|x| x.name.as_str())
+        .collect();
+    if !empty.is_empty() {
+        info_message(log, format!("empty pages: {}.", empty.join(", ")));
+    }
 }
 
 pub fn prepare_sources<P: AsRef<Path>>(
@@ -104,7 +360,19 @@ pub fn prepare_sources<P: AsRef<Path>>(
         None => &Default::default(),
     };
     let mut info = sources::source_list_from_paths(sources, extensions)?;
+    if let Some(threshold) = settings.trim {
+        sources::trim_textures(&mut info, threshold)?;
+    }
+    sort_sources(&mut info, settings.sorting);
+    sources::solve_name_collisions(&mut info);
+    if settings.deduplicate {
+        sources::deduplicate_textures(&mut info, settings.deduplicate_flips)?;
+    }
+    //return the vector with all the source texture information
+    Ok(info)
+}
 
+pub fn sort_sources(info: &mut [sources::SourceTexture], sorting: packing::SortingMethod) {
     use sources::SourceTexture;
     fn short_side_sort(a: &SourceTexture, b: &SourceTexture) -> cmp::Ordering {
         cmp::min(b.dimensions.width, b.dimensions.height)
@@ -116,7 +384,7 @@ pub fn prepare_sources<P: AsRef<Path>>(
             .cmp(&cmp::max(a.dimensions.width, a.dimensions.height))
     }
     //sort the textures according to the settings
-    match settings.sorting {
+    match sorting {
         packing::SortingMethod::ShortSide => info.sort_by(|a, b| {
             short_side_sort(a, b)
                 .then(long_side_sort(a, b))
@@ -128,23 +396,18 @@ pub fn prepare_sources<P: AsRef<Path>>(
                 .then(human_sort::compare(&a.name, &b.name))
         }),
     }
-    sources::solve_name_collisions(&mut info);
-    if settings.deduplicate {
-        sources::deduplicate_textures(&mut info)?;
-    }
-    //return the vector with all the source texture information
-    Ok(info)
 }
 
 pub fn generate_image_files<P: AsRef<Path>>(
-    destination: P, packer: TexturePacker, overwrite: bool, log: &mut Option<impl Write>,
+    destination: P, packer: TexturePacker, overwrite: bool, filters: images::PageFilters,
+    log: &mut Option<impl Write>,
 ) -> utils::GeneralResult<()> {
     for page in packer.pages.into_iter() {
         let image_path = Path::new(destination.as_ref()).join(format!("{}.png", &page.name));
         if let Some(msg) = outputs::notify_overwrite(&image_path, overwrite)? {
             info_message(log, msg);
         }
-        images::generate_image(page, &image_path)?;
+        images::generate_image(page, &image_path, filters)?;
     }
     Ok(())
 }
@@ -152,6 +415,13 @@ pub fn generate_image_files<P: AsRef<Path>>(
 fn generate_output_files(
     args: &interface::PackArguments, packer: TexturePacker, log: &mut Option<impl Write>,
 ) -> utils::GeneralResult<()> {
+    //nothing is written unless every page can be saved as an image
+    if let Some(page) = packer.pages.iter().find(|x| {
+        let (w, h) = x.dimensions();
+        w == 0 || h == 0
+    }) {
+        return Err(format!("page '{}' has no area, nothing was written.", page.name).into());
+    }
     let destination =
         outputs::prepare_output_directory(&args.output, outputs::PathType::Files, log)?;
     let extension = match args.format {
@@ -162,11 +432,20 @@ fn generate_output_files(
     if let Some(msg) = outputs::notify_overwrite(&description_file, args.overwrite)? {
         info_message(log, msg);
     }
-    if let Some(description) = atlas::generate_description(args, &packer) {
+    //settings picked automatically are recorded in the description
+    let settings = match args.auto {
+        true => Some(query::describe_settings(args)),
+        false => None,
+    };
+    if let Some(description) = atlas::generate_description(args, &packer, settings) {
         let mut description_handle = File::create(&description_file)?;
         description_handle.write_all(description.as_bytes())?;
     } else {
         return Err("unable to generate description file".into());
     }
-    generate_image_files(destination, packer, args.overwrite, log)
+    let filters = images::PageFilters {
+        bleed: args.bleed,
+        premultiply: args.premultiply,
+    };
+    generate_image_files(destination, packer, args.overwrite, filters, log)
 }
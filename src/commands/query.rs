@@ -1,53 +1,221 @@
-use std::io::Write;
+use std::{
+    cmp,
+    io::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use indicatif::ProgressBar;
+use serde::Serialize;
 
 use super::pack;
-use crate::{interface, utils};
+use crate::{
+    interface::{self, GuillotineSplit, PackingEngine, PlacementMethod, QueryFormat, SkylineFit},
+    packing::{self, TexturePacker},
+    sources::SourceTexture,
+    utils,
+};
+
+pub type QueryResult = (interface::PackArguments, TexturePacker, f64, Duration);
 
 pub fn query(
     args: &interface::QueryArguments, log: &mut Option<impl Write>,
 ) -> utils::GeneralResult<()> {
-    let mut results = Vec::new();
-    let pack_arguments_list = generate_mock_packing_arguments(args);
-    for p in pack_arguments_list.into_iter() {
-        let mut sink = if log.is_some() {
-            Some(std::io::sink())
-        } else {
-            None
+    let page_setups = generate_mock_packing_arguments(args);
+    //the sources are scanned and deduplicated only once,
+    //every candidate sorts its own copy of them later
+    let settings = match page_setups
+        .iter()
+        .find_map(|x| packing::generate_packing_settings(x).ok())
+    {
+        Some(x) => x,
+        //if none of the setups can be used, the first one's problem is reported
+        None => packing::generate_packing_settings(&page_setups[0])?,
+    };
+    let sources = pack::prepare_sources(&args.sources, &pack::EXTENSIONS, &settings)?;
+    //page setups that are too small for some of the sources are left out
+    let mut pack_arguments_list = Vec::new();
+    let mut misfit = None;
+    for setup in page_setups.iter() {
+        //e.g. page sizes that aren't powers of two can't be used with --po2
+        let settings = match packing::generate_packing_settings(setup) {
+            Ok(x) => x,
+            Err(msg) => {
+                utils::info_message(log, format!("skipping {}, {}", describe_page(setup), msg));
+                misfit.get_or_insert(msg);
+                continue;
+            }
         };
-        utils::info_message(log, format!("packing with {}", describe_settings(&p)));
-        let packer = pack::pack_textures(&p, &mut sink)?;
-        let efficiency = packer.efficiency();
-        results.push((p, packer, efficiency));
+        match pack::validate_sources(&sources, &settings) {
+            Ok(_) => pack_arguments_list.extend(generate_candidates(setup)),
+            Err(msg) => {
+                utils::info_message(
+                    log,
+                    format!("skipping {}, some sources don't fit.", describe_page(setup)),
+                );
+                misfit.get_or_insert(msg);
+            }
+        }
     }
-    //sort results by efficiency
-    results.sort_unstable_by(|a, b| b.2.total_cmp(&a.2));
+    if let (true, Some(msg)) = (pack_arguments_list.is_empty(), misfit) {
+        return Err(msg);
+    }
+    let mut results = pack_candidates(pack_arguments_list, &sources, "query", log)?;
+    rank_results(&mut results);
+    match args.format {
+        Some(format) => print_records(&results, format),
+        None => {
+            utils::info_message(log, generate_report(&results, args.pages));
+            Ok(())
+        }
+    }
+}
+
+/// One row of the machine-readable query output
+#[derive(Serialize)]
+struct QueryRecord {
+    settings: String,
+    arguments: String,
+    pages: usize,
+    width: u32,
+    height: u32,
+    area: u64,
+    efficiency: f64,
+    time: f64,
+}
+
+fn print_records(results: &[QueryResult], format: QueryFormat) -> utils::GeneralResult<()> {
+    let records: Vec<QueryRecord> = results
+        .iter()
+        .map(|(args, packer, efficiency, time)| {
+            let (width, height) = packer.page_size();
+            QueryRecord {
+                settings: describe_settings(args),
+                arguments: describe_args(args).trim_end().to_owned(),
+                pages: packer.pages.len(),
+                width,
+                height,
+                area: packer.total_packed_area(),
+                efficiency: *efficiency,
+                time: time.as_secs_f64(),
+            }
+        })
+        .collect();
+    let output = match format {
+        QueryFormat::Json => serde_json::to_string_pretty(&records)?,
+        QueryFormat::Csv => {
+            //text fields are always quoted, since the settings contain commas
+            let quote = |x: &str| format!("\"{}\"", x.replace('"', "\"\""));
+            let mut r = String::from("settings,arguments,pages,width,height,area,efficiency,time");
+            for x in records.iter() {
+                r += format!(
+                    "\n{},{},{},{},{},{},{:.4},{:.4}",
+                    quote(&x.settings),
+                    quote(&x.arguments),
+                    x.pages,
+                    x.width,
+                    x.height,
+                    x.area,
+                    x.efficiency,
+                    x.time
+                )
+                .as_str();
+            }
+            r
+        }
+    };
+    writeln!(std::io::stdout(), "{output}")?;
+    Ok(())
+}
+
+fn generate_report(results: &[QueryResult], pages: Option<usize>) -> String {
     //build a string buffer with the report
-    let ruler_width = 72;
+    //the settings column is as wide as the longest description
+    let descriptions: Vec<String> = results.iter().map(|x| describe_settings(&x.0)).collect();
+    let column = descriptions
+        .iter()
+        .map(|x| x.len())
+        .fold("SETTINGS".len(), cmp::max)
+        + 4;
+    let ruler_width = column + 38;
     let mut report = String::new();
     report += format!(
-        "\n{0:<40}{1:>16}{2:>16}\n",
-        "SETTINGS", "SIZE", "EFFICIENCY"
+        "\n{0:<column$}{1:>16}{2:>12}{3:>10}\n",
+        "SETTINGS", "SIZE", "EFFICIENCY", "TIME"
     )
     .as_str();
     report += format!("{}\n", "-".repeat(ruler_width)).as_str();
-    results.iter().for_each(|x| {
-        let (settings, packer, efficiency) = x;
-        let (count, (w, h)) = (packer.pages.len(), packer.page_size());
-        report += format!(
-            "{0:<40}{1:>16}{2:>16}\n",
-            describe_settings(settings),
-            format!("{}p, {}x{}", count, w, h),
-            format!("{:.2}%", efficiency)
-        )
-        .as_str();
-    });
+    results
+        .iter()
+        .zip(descriptions)
+        .for_each(|(x, description)| {
+            let (_, packer, efficiency, time) = x;
+            let (count, (w, h)) = (packer.pages.len(), packer.page_size());
+            report += format!(
+                "{0:<column$}{1:>16}{2:>12}{3:>10}\n",
+                description,
+                format!("{}p, {}x{}", count, w, h),
+                format!("{:.2}%", efficiency),
+                format!("{:.3}s", time.as_secs_f64())
+            )
+            .as_str();
+        });
     report += format!("{}\n", "-".repeat(ruler_width)).as_str();
-    report += "for the most efficient packing of these sources, use this command:\n";
+    //the results are ranked by page count first
+    let smallest = results
+        .iter()
+        .reduce(|a, b| if b.2 > a.2 { b } else { a })
+        .unwrap();
+    match std::ptr::eq(smallest, &results[0]) {
+        true => report += "for the most efficient packing of these sources, use this command:\n",
+        false => {
+            report += "for the fewest pages, use this command:\n";
+            report += format!(
+                "\tatlas pack {}[sources] [output]\n\n",
+                describe_args(&results[0].0)
+            )
+            .as_str();
+            report += "for the least total area, use this command:\n";
+        }
+    }
     report += format!(
         "\tatlas pack {}[sources] [output]\n\n",
-        describe_args(&results[0].0)
+        describe_args(&smallest.0)
     )
     .as_str();
+    //the smallest pages that still fit everything in the given count
+    if let Some(pages) = pages {
+        let fitting = results
+            .iter()
+            .filter(|x| x.1.pages.len() <= pages)
+            .min_by_key(|x| {
+                let (w, h) = x.1.page_size();
+                ((w as u64) * (h as u64), x.1.total_packed_area())
+            });
+        let plural = if pages == 1 { "" } else { "s" };
+        match fitting {
+            Some((args, packer, _, _)) => {
+                let (w, h) = packer.page_size();
+                report += format!(
+                    "the smallest page size that fits these sources in {pages} page{plural} is {w}x{h}:\n"
+                )
+                .as_str();
+                report +=
+                    format!("\tatlas pack {}[sources] [output]\n\n", describe_args(args)).as_str();
+            }
+            None => {
+                report += format!(
+                    "none of the page sizes fit these sources in {pages} page{plural}.\n\n"
+                )
+                .as_str();
+            }
+        }
+    }
     report += "if texture rotation is not allowed, use this command:\n";
     report += format!(
         "\tatlas pack {}[sources] [output]\n",
@@ -55,68 +223,353 @@ pub fn query(
     )
     .as_str();
     report += "-".repeat(ruler_width).as_str();
-    utils::info_message(log, report);
-    Ok(())
+    report
 }
 
-fn describe_args(args: &interface::PackArguments) -> String {
+/// Sorts the results by page count, then by efficiency
+pub fn rank_results(results: &mut [QueryResult]) {
+    results.sort_by(|a, b| {
+        a.1.pages
+            .len()
+            .cmp(&b.1.pages.len())
+            .then(b.2.total_cmp(&a.2))
+    });
+}
+
+pub fn pack_candidates(
+    candidates: Vec<interface::PackArguments>, sources: &[SourceTexture], label: &str,
+    log: &mut Option<impl Write>,
+) -> utils::GeneralResult<Vec<QueryResult>> {
+    if candidates.is_empty() {
+        return Err("there are no candidates to pack.".into());
+    }
+    let workers = thread::available_parallelism().map_or(1, |x| x.get());
+    let workers = workers.clamp(1, candidates.len());
+    utils::info_message(
+        log,
+        format!(
+            "packing {} candidates on {} thread{}",
+            candidates.len(),
+            workers,
+            if workers == 1 { "" } else { "s" }
+        ),
+    );
+    //each worker keeps taking the next candidate
+    //from the list until there are none left
+    let next = AtomicUsize::new(0);
+    let (send, recv) = mpsc::channel();
+    let mut results = thread::scope(|scope| {
+        for _ in 0..workers {
+            let (send, next, candidates) = (send.clone(), &next, &candidates);
+            scope.spawn(move || {
+                loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(args) = candidates.get(idx) else {
+                        break;
+                    };
+                    let start = Instant::now();
+                    let packer = pack_candidate(args, sources, label);
+                    let _ = send.send((idx, packer, start.elapsed()));
+                }
+            });
+        }
+        drop(send);
+        let bar = log
+            .as_ref()
+            .map(|_| ProgressBar::new(candidates.len() as u64));
+        let mut results = Vec::new();
+        while let Ok(result) = recv.recv() {
+            if let Some(bar) = bar.as_ref() {
+                bar.inc(1);
+            }
+            results.push(result);
+        }
+        if let Some(bar) = bar {
+            bar.finish_and_clear();
+        }
+        results
+    });
+    //put the results back in the order of the candidates
+    results.sort_by_key(|x| x.0);
+    //candidates that fail to pack are left out, unless they all do
+    let mut packed = Vec::new();
+    let mut failure = None;
+    for (args, (_, packer, time)) in candidates.into_iter().zip(results) {
+        match packer {
+            Ok(packer) => {
+                let efficiency = packer.efficiency();
+                packed.push((args, packer, efficiency, time));
+            }
+            Err(msg) => {
+                utils::info_message(
+                    log,
+                    format!("skipping {}, {}", describe_settings(&args), msg),
+                );
+                failure = Some(msg);
+            }
+        }
+    }
+    match (packed.is_empty(), failure) {
+        (true, Some(msg)) => Err(msg),
+        _ => Ok(packed),
+    }
+}
+
+fn pack_candidate(
+    args: &interface::PackArguments, sources: &[SourceTexture], label: &str,
+) -> utils::GeneralResult<TexturePacker> {
+    let settings = packing::generate_packing_settings(args)?;
+    let mut sources = sources.to_vec();
+    if let Some(treatment) = settings.source_treatment.as_ref() {
+        pack::sort_sources(&mut sources, treatment.sorting);
+    }
+    let mut packer = TexturePacker::new(label, sources, settings);
+    pack::run_packer(&mut packer, None)?;
+    Ok(packer)
+}
+
+pub fn describe_args(args: &interface::PackArguments) -> String {
     let mut r = String::new();
+    if let Some(spacing) = args.spacing.filter(|x| *x > 0) {
+        r += format!("-s {spacing} ").as_str();
+    }
+    if let Some(size) = args.page_size.as_ref() {
+        r += format!("-p {size} ").as_str();
+    }
+    r += if args.power_of_two { "--po2 " } else { "" };
     r += if args.short_side_sort { "--short " } else { "" };
-    r += if args.pack_by_area { "--area " } else { "" };
+    r += if args.global_best_fit {
+        "--global "
+    } else {
+        ""
+    };
+    match args.engine {
+        Some(PackingEngine::Skyline) => {
+            r += "--engine skyline ";
+            if let Some(fit) = args.skyline_fit.filter(|x| *x != SkylineFit::BottomLeft) {
+                r += format!("--skyline-fit {} ", value_name(fit)).as_str();
+            }
+        }
+        Some(PackingEngine::Guillotine) => {
+            r += "--engine guillotine ";
+            if let Some(split) = args.split.filter(|x| *x != GuillotineSplit::ShorterAxis) {
+                r += format!("--split {} ", value_name(split)).as_str();
+            }
+            r += if args.merge { "--merge " } else { "" };
+        }
+        _ => {}
+    }
+    //the skyline engine doesn't use the placement method
+    if args.engine != Some(PackingEngine::Skyline) {
+        r += match args.method {
+            None | Some(PlacementMethod::Distance) => String::new(),
+            Some(PlacementMethod::Area) => String::from("--area "),
+            Some(m) => format!("--method {} ", value_name(m)),
+        }
+        .as_str();
+    }
     r += if args.rotate { "--rotate " } else { "" };
     r
 }
 
-fn describe_settings(args: &interface::PackArguments) -> String {
-    let sorting = match args.short_side_sort {
-        true => "short side",
-        false => "long side",
+pub fn describe_settings(args: &interface::PackArguments) -> String {
+    let sorting = match (args.global_best_fit, args.short_side_sort) {
+        (true, _) => "global best fit",
+        (false, true) => "short side",
+        (false, false) => "long side",
+    };
+    let method = match args.method {
+        None | Some(PlacementMethod::Distance) => "distance",
+        Some(PlacementMethod::Area) => "area",
+        Some(PlacementMethod::ShortSideFit) => "short side fit",
+        Some(PlacementMethod::LongSideFit) => "long side fit",
+        Some(PlacementMethod::BottomLeft) => "bottom-left",
+        Some(PlacementMethod::ContactPoint) => "contact point",
     };
-    let packing = match args.pack_by_area {
-        true => "area",
-        false => "distance",
+    let packing = match args.engine {
+        Some(PackingEngine::Skyline) => format!(
+            "skyline {}",
+            value_name(args.skyline_fit.unwrap_or(SkylineFit::BottomLeft))
+        ),
+        Some(PackingEngine::Guillotine) => format!(
+            "guillotine {}, {method}",
+            value_name(args.split.unwrap_or(GuillotineSplit::ShorterAxis))
+        ),
+        _ => String::from(method),
     };
     let rotation = match args.rotate {
         true => "rotation",
         false => "no rotation",
     };
-    format!("{sorting}, {packing}, {rotation}")
+    match (
+        args.page_size.is_some(),
+        args.spacing.unwrap_or(0) > 0,
+        args.power_of_two,
+    ) {
+        (false, false, false) => format!("{sorting}, {packing}, {rotation}"),
+        _ => format!("{sorting}, {packing}, {rotation}, {}", describe_page(args)),
+    }
 }
 
+fn describe_page(args: &interface::PackArguments) -> String {
+    let mut r = match (args.page_size.as_ref(), args.power_of_two) {
+        (Some(size), _) => format!("{size} pages"),
+        (None, true) => String::from("po2 pages"),
+        (None, false) => String::from("dynamic pages"),
+    };
+    if let Some(spacing) = args.spacing.filter(|x| *x > 0) {
+        r += format!(", {spacing}px spacing").as_str();
+    }
+    r
+}
+
+fn value_name<T: ValueEnum>(value: T) -> String {
+    //every variant of the CLI enums has a name
+    value.to_possible_value().unwrap().get_name().to_owned()
+}
+
+/// The arguments shared by all the candidates for each page setup
 fn generate_mock_packing_arguments(
     query_args: &interface::QueryArguments,
 ) -> Vec<interface::PackArguments> {
-    let sorting_options = [Some(false), Some(true)];
-    let packing_options = [false, true];
-    let rotate_options = [false, true];
-    let default_settings = interface::PackArguments {
+    let spacing_options = match query_args.spacing.is_empty() {
+        true => vec![None],
+        false => query_args.spacing.iter().map(|x| Some(*x)).collect(),
+    };
+    let page_size_options = match query_args.page_size.is_empty() {
+        true => vec![None],
+        false => query_args
+            .page_size
+            .iter()
+            .map(|x| Some(x.clone()))
+            .collect(),
+    };
+    let mut r = Vec::new();
+    for spacing in spacing_options {
+        for page_size in page_size_options.iter() {
+            //with --po2, dynamic pages are tried both ways, fixed
+            //pages already have the size they'll be generated with
+            let po2_options = match (query_args.power_of_two, page_size) {
+                (true, None) => vec![false, true],
+                (po2, _) => vec![po2],
+            };
+            for power_of_two in po2_options.iter() {
+                r.push(generate_page_setup(
+                    query_args,
+                    spacing,
+                    page_size.clone(),
+                    *power_of_two,
+                ));
+            }
+        }
+    }
+    r
+}
+
+fn generate_page_setup(
+    query_args: &interface::QueryArguments, spacing: Option<u32>, page_size: Option<String>,
+    power_of_two: bool,
+) -> interface::PackArguments {
+    interface::PackArguments {
         sources: query_args.sources.clone(),
         output: String::from("query"),
         overwrite: false,
-        spacing: query_args.spacing,
-        page_size: query_args.page_size.clone(),
+        spacing,
+        extrude: None,
+        padding: None,
+        border: None,
+        align: None,
+        page_size,
         quiet: false,
         format: None,
         pack_by_area: false,
+        method: None,
+        engine: None,
+        skyline_fit: None,
+        split: None,
+        merge: false,
         short_side_sort: false,
         rotate: false,
-        power_of_two: false,
+        global_best_fit: false,
+        power_of_two,
+        search: None,
+        search_iterations: None,
+        seed: None,
+        shrink_last_page: false,
+        optimize_size: false,
+        square: false,
+        max_size: None,
         include_duplicates: query_args.include_duplicates,
-    };
+        dedup_flips: false,
+        trim: false,
+        trim_threshold: None,
+        bleed: false,
+        premultiply: false,
+        previous: None,
+        pin: Vec::new(),
+        pins: None,
+        auto: false,
+    }
+}
+
+/// Every combination of settings worth trying, on top of the given arguments
+pub fn generate_candidates(
+    default_settings: &interface::PackArguments,
+) -> Vec<interface::PackArguments> {
+    let sorting_options = [Some(false), Some(true)];
+    let rotate_options = [false, true];
+    //every engine, with each of its heuristics
+    let mut packing_options = Vec::new();
+    for method in PlacementMethod::value_variants() {
+        packing_options.push(interface::PackArguments {
+            engine: Some(PackingEngine::MaxRects),
+            method: Some(*method),
+            ..default_settings.clone()
+        });
+    }
+    for fit in SkylineFit::value_variants() {
+        packing_options.push(interface::PackArguments {
+            engine: Some(PackingEngine::Skyline),
+            skyline_fit: Some(*fit),
+            ..default_settings.clone()
+        });
+    }
+    for split in GuillotineSplit::value_variants() {
+        packing_options.push(interface::PackArguments {
+            engine: Some(PackingEngine::Guillotine),
+            method: Some(PlacementMethod::Area),
+            split: Some(*split),
+            merge: true,
+            ..default_settings.clone()
+        });
+    }
 
     let mut r = Vec::new();
     for sort in sorting_options {
-        for pack in packing_options {
+        for pack in packing_options.iter() {
             for rotation in rotate_options {
                 r.push(interface::PackArguments {
                     //this is always Some()
                     short_side_sort: sort.unwrap(),
-                    pack_by_area: pack,
                     rotate: rotation,
-                    ..default_settings.clone()
+                    ..pack.clone()
                 })
             }
         }
     }
+    //global best fit doesn't depend much on the sorting,
+    //so it's only tested with the MAXRECTS heuristics
+    for pack in packing_options
+        .iter()
+        .filter(|x| x.engine == Some(PackingEngine::MaxRects))
+    {
+        for rotation in rotate_options {
+            r.push(interface::PackArguments {
+                global_best_fit: true,
+                rotate: rotation,
+                ..pack.clone()
+            })
+        }
+    }
     r
 }
@@ -0,0 +1,196 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use image::GenericImage;
+use serde::Serialize;
+
+use super::pack;
+use crate::{
+    images, interface, outputs,
+    packing::{self, PackingMethod, TexturePacker},
+    rectangle::Rect,
+    sources::{self, FlipData, SourceTexture},
+    utils::{self, info_message},
+};
+
+/// The unique tiles of a set of images, and which
+/// of them (and how flipped) goes in each cell
+struct SlicedImages {
+    tiles: Vec<image::RgbaImage>,
+    maps: Vec<TilemapImage>,
+}
+
+/// The description of a tileset, and of the images made out of its tiles
+#[derive(Serialize)]
+struct Tilemap {
+    texture: String,
+    tile_width: u32,
+    tile_height: u32,
+    columns: u32,
+    tiles: usize,
+    maps: Vec<TilemapImage>,
+}
+
+/// One of the source images, as rows of tiles from left to right
+#[derive(Serialize)]
+struct TilemapImage {
+    name: String,
+    width: u32,
+    height: u32,
+    columns: u32,
+    rows: u32,
+    cells: Vec<TilemapCell>,
+}
+
+/// A tile in the tileset, and how it's flipped in this cell (see `FlipData`)
+#[derive(Serialize)]
+struct TilemapCell {
+    tile: usize,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    flip_h: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    flip_v: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    flip_d: bool,
+}
+
+pub fn tileset(
+    args: &interface::TilesetArguments, log: &mut Option<impl Write>,
+) -> utils::GeneralResult<()> {
+    let tile_size = match packing::read_dimensions(&args.tile_size) {
+        Some((w, h)) if w > 0 && h > 0 => (w, h),
+        _ => return Err(format!("failed to read tile size from '{}'.", args.tile_size).into()),
+    };
+    let label = match PathBuf::from(&args.output).file_stem() {
+        Some(stem) => stem.to_string_lossy().to_string(),
+        None => return Err(format!("unable to extract filename from '{}'.", args.output).into()),
+    };
+    let mut source_images = sources::source_list_from_paths(&args.sources, &pack::EXTENSIONS)?;
+    source_images.sort_by(|a, b| human_sort::compare(&a.name, &b.name));
+    sources::solve_name_collisions(&mut source_images);
+    let sliced = slice_images(&source_images, tile_size, args.flips)?;
+    let cell_count: usize = sliced.maps.iter().map(|x| x.cells.len()).sum();
+    info_message(
+        log,
+        format!(
+            "sliced {} images into {} tiles, {} of them unique.",
+            source_images.len(),
+            cell_count,
+            sliced.tiles.len()
+        ),
+    );
+    //arrange the unique tiles in a grid as square as possible
+    let columns = (sliced.tiles.len() as f64).sqrt().ceil() as u32;
+    let rows = (sliced.tiles.len() as u32).div_ceil(columns);
+    let tiles = (0..sliced.tiles.len()).map(|i| SourceTexture {
+        name: i.to_string(),
+        path: PathBuf::from(i.to_string()),
+        dimensions: Rect::new(0, 0, tile_size.0, tile_size.1),
+        replica_of: None,
+        packing: None,
+        trim: None,
+        flip: None,
+    });
+    let settings = packing::generate_layout_settings((columns, rows), PackingMethod::Rows);
+    let mut packer = TexturePacker::new(&label, tiles, settings);
+    packer.arrange_everything(None)?;
+    let page = &packer.pages[0];
+    //the index of each tile in the tileset comes from its position in the grid
+    let mut indices = vec![0; sliced.tiles.len()];
+    let mut parts = Vec::new();
+    for texture in page.textures.iter() {
+        let tile: usize = texture.name.parse()?;
+        let position = &texture.packing.as_ref().unwrap().position;
+        let (x, y) = (position.x / tile_size.0, position.y / tile_size.1);
+        indices[tile] = (y * columns + x) as usize;
+        parts.push((&sliced.tiles[tile], position.x, position.y));
+    }
+    let mut maps = sliced.maps;
+    maps.iter_mut()
+        .flat_map(|x| x.cells.iter_mut())
+        .for_each(|x| x.tile = indices[x.tile]);
+    //write the tileset and its description
+    let destination =
+        outputs::prepare_output_directory(&args.output, outputs::PathType::Files, log)?;
+    let image_path = Path::new(&destination).join(format!("{label}.png"));
+    let description_path = Path::new(&destination).join(format!("{label}.json"));
+    for path in [&image_path, &description_path] {
+        if let Some(msg) = outputs::notify_overwrite(path, args.overwrite)? {
+            info_message(log, msg);
+        }
+    }
+    let tilemap = Tilemap {
+        texture: format!("{label}.png"),
+        tile_width: tile_size.0,
+        tile_height: tile_size.1,
+        columns,
+        tiles: sliced.tiles.len(),
+        maps,
+    };
+    let mut description_handle = File::create(&description_path)?;
+    description_handle.write_all(serde_json::to_string_pretty(&tilemap)?.as_bytes())?;
+    let (w, h) = page.dimensions();
+    images::compose_image((w, h), &parts, &image_path)?;
+    info_message(log, format!("generated tileset, size {w}x{h}."));
+    Ok(())
+}
+
+fn slice_images(
+    sources: &[SourceTexture], (tw, th): (u32, u32), include_flips: bool,
+) -> utils::GeneralResult<SlicedImages> {
+    //tiles can only be flipped diagonally if they're square
+    let flips: Vec<FlipData> = match include_flips {
+        true => FlipData::all()
+            .filter(|x| !x.diagonal || tw == th)
+            .collect(),
+        false => vec![FlipData::default()],
+    };
+    let mut tiles: Vec<image::RgbaImage> = Vec::new();
+    let mut hashes = HashMap::<u64, Vec<usize>>::new();
+    let mut maps = Vec::new();
+    for src in sources.iter() {
+        let image = image::open(&src.path)?.into_rgba8();
+        let (width, height) = image.dimensions();
+        let (columns, rows) = (width.div_ceil(tw), height.div_ceil(th));
+        let mut cells = Vec::new();
+        for (x, y) in (0..rows).flat_map(|y| (0..columns).map(move |x| (x * tw, y * th))) {
+            //the tiles along the right and bottom edges may be partly empty
+            let mut tile = image::RgbaImage::new(tw, th);
+            let view =
+                image::imageops::crop_imm(&image, x, y, tw.min(width - x), th.min(height - y));
+            tile.copy_from(&*view, 0, 0)?;
+            let bucket = hashes
+                .entry(sources::flipped_hash(&tile, &flips))
+                .or_default();
+            let found =
+                sources::find_flipped(&tile, bucket.iter().map(|&i| (i, &tiles[i])), &flips);
+            let (idx, flip) = match found {
+                Some(x) => x,
+                None => {
+                    bucket.push(tiles.len());
+                    tiles.push(tile);
+                    (tiles.len() - 1, FlipData::default())
+                }
+            };
+            cells.push(TilemapCell {
+                tile: idx,
+                flip_h: flip.horizontal,
+                flip_v: flip.vertical,
+                flip_d: flip.diagonal,
+            });
+        }
+        maps.push(TilemapImage {
+            name: src.name.clone(),
+            width,
+            height,
+            columns,
+            rows,
+            cells,
+        });
+    }
+    Ok(SlicedImages { tiles, maps })
+}
@@ -9,9 +9,9 @@ use std::{
 use indicatif::ProgressBar;
 use utils::info_message;
 
-use crate::{atlas, images, interface, outputs, sources, utils};
+use crate::{atlas, images, interface, outputs, utils};
 
-type UnpackedAtlas = Vec<(String, Vec<sources::SourceTexture>)>;
+type UnpackedAtlas = Vec<atlas::DescribedPage>;
 
 pub fn unpack(
     args: &interface::UnpackArguments, log: &mut Option<impl Write>,
@@ -28,7 +28,7 @@ pub fn unpack(
         let mut t = vec![];
         // textures is a Vec of Vecs
         textures.iter().for_each(|x| {
-            x.1.iter().for_each(|x| {
+            x.2.iter().for_each(|x| {
                 t.push(output_path.join(&x.path));
             })
         });
@@ -47,7 +47,12 @@ pub fn unpack(
             }
         }
     }
-    unpack_with_progress_bar((textures_path, output_path), textures, log)
+    unpack_with_progress_bar(
+        (textures_path, output_path),
+        textures,
+        args.premultiply,
+        log,
+    )
 }
 
 fn gather_textures_from_source(
@@ -98,11 +103,11 @@ fn check_missing_textures<P: AsRef<Path>>(
 }
 
 fn fix_name_conflicts(mut textures: UnpackedAtlas) -> UnpackedAtlas {
-    let texture_count: usize = textures.iter().map(|x| x.1.len()).sum();
+    let texture_count: usize = textures.iter().map(|x| x.2.len()).sum();
     let mut unique_names = HashSet::new();
     'duplicates: loop {
         unique_names.clear();
-        for page in textures.iter_mut().map(|x| &mut x.1) {
+        for page in textures.iter_mut().map(|x| &mut x.2) {
             for e in page.iter_mut() {
                 if unique_names.contains(&e.path) {
                     e.path = utils::append_to_filename(&e.path, "_(copy)");
@@ -119,14 +124,16 @@ fn fix_name_conflicts(mut textures: UnpackedAtlas) -> UnpackedAtlas {
 }
 
 fn unpack_with_progress_bar(
-    (src, dst): (PathBuf, PathBuf), pages: UnpackedAtlas, log: &mut Option<impl Write>,
+    (src, dst): (PathBuf, PathBuf), pages: UnpackedAtlas, premultiply: bool,
+    log: &mut Option<impl Write>,
 ) -> utils::GeneralResult<()> {
-    let count: usize = pages.iter().map(|x| x.1.len()).sum();
+    let count: usize = pages.iter().map(|x| x.2.len()).sum();
     let (send, recv) = mpsc::channel::<u64>();
     let handle = thread::spawn(move || -> utils::GeneralResult<()> {
         for page in pages.iter() {
             let source = src.join(&page.0);
-            images::unpack_page((&source, &dst), &page.1, Some(&send))?;
+            let alpha = (page.1, premultiply);
+            images::unpack_page((&source, &dst), &page.2, alpha, Some(&send))?;
         }
         Ok(())
     });
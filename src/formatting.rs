@@ -1,9 +1,9 @@
-use crate::atlas::{self, AtlasPage};
+use crate::atlas::{self, AtlasPage, DescribedPage};
 use crate::sources::SourceTexture;
 
 pub trait AtlasFormatter {
     fn format_atlas(&self, pages: &[AtlasPage]) -> Option<String>;
-    fn read_atlas(&self, source: &str) -> Option<Vec<(String, Vec<SourceTexture>)>>;
+    fn read_atlas(&self, source: &str) -> Option<Vec<DescribedPage>>;
 }
 
 pub struct JsonFormatter;
@@ -18,15 +18,15 @@ impl AtlasFormatter for JsonFormatter {
         .ok()
     }
 
-    fn read_atlas(&self, source: &str) -> Option<Vec<(String, Vec<SourceTexture>)>> {
+    fn read_atlas(&self, source: &str) -> Option<Vec<DescribedPage>> {
         let mut r = Vec::new();
         if let Ok(page) = serde_json::from_str::<AtlasPage>(source) {
             let t = page.regions.into_iter().map(SourceTexture::from).collect();
-            r.push((page.texture, t));
+            r.push((page.texture, page.premultiplied, t));
         } else if let Ok(pages) = serde_json::from_str::<Vec<AtlasPage>>(source) {
             for page in pages.into_iter() {
                 let t = page.regions.into_iter().map(SourceTexture::from).collect();
-                r.push((page.texture, t));
+                r.push((page.texture, page.premultiplied, t));
             }
         } else {
             return None;
@@ -38,11 +38,18 @@ impl AtlasFormatter for JsonFormatter {
 impl AtlasFormatter for TextFormatter {
     fn format_atlas(&self, pages: &[AtlasPage]) -> Option<String> {
         let mut buffer = String::new();
-        buffer += "# page <name> <width> <height>\n";
-        buffer += "# region <name> <x> <y> <width> <height> [<rotated> <original_width> <original_height>]\n";
+        buffer += "# page <name> <width> <height> [<premultiplied>]\n";
+        buffer += "# region <name> <x> <y> <width> <height> [<rotated> <original_width> <original_height>]";
+        buffer += " [<offset_x> <offset_y> <source_width> <source_height>] [flip:<h><v><d>]\n";
+        if let Some(settings) = pages.first().and_then(|x| x.settings.as_ref()) {
+            buffer += format!("# settings {settings}\n").as_str();
+        }
         for page in pages {
-            buffer +=
-                format!("page \"{}\" {} {}\n", page.texture, page.width, page.height).as_str();
+            let mut line = format!("page \"{}\" {} {}", page.texture, page.width, page.height);
+            if page.premultiplied {
+                line += " 1";
+            }
+            buffer += format!("{line}\n").as_str();
             for region in page.regions.iter() {
                 let (name, x, y, w, h) = (
                     &region.name,
@@ -56,28 +63,55 @@ impl AtlasFormatter for TextFormatter {
                     .as_ref()
                     .map(|x| (x.rotated, x.original_width, x.original_height));
                 let mut line = format!("region \"{name}\" {x} {y} {w} {h}");
-                line += match extra {
-                    Some((r, ow, oh)) => format!(" {} {} {}\n", if r { 1 } else { 0 }, ow, oh),
-                    None => String::from("\n"),
+                if let Some((r, ow, oh)) = extra {
+                    line += format!(" {} {} {}", if r { 1 } else { 0 }, ow, oh).as_str();
+                }
+                if let Some(t) = region.trim.as_ref() {
+                    let (ox, oy, sw, sh) =
+                        (t.offset_x, t.offset_y, t.source_width, t.source_height);
+                    line += format!(" {ox} {oy} {sw} {sh}").as_str();
                 }
-                .as_str();
+                if let Some(f) = region.flip.as_ref() {
+                    let flips = [(f.flip_h, 'h'), (f.flip_v, 'v'), (f.flip_d, 'd')];
+                    line += " flip:";
+                    line.extend(flips.iter().filter(|x| x.0).map(|x| x.1));
+                }
+                line += "\n";
                 buffer += line.as_str();
             }
         }
         Some(buffer)
     }
 
-    fn read_atlas(&self, source: &str) -> Option<Vec<(String, Vec<SourceTexture>)>> {
+    fn read_atlas(&self, source: &str) -> Option<Vec<DescribedPage>> {
         let mut result = vec![];
         for line in source.lines() {
             let elements: Vec<&str> = line.split(' ').collect();
             match elements[0] {
                 "#" => continue,
                 "page" => {
-                    result.push((String::from(elements[1]).replace('"', ""), Vec::new()));
+                    let premultiplied = elements.get(4).is_some_and(|x| *x == "1");
+                    result.push((
+                        String::from(elements[1]).replace('"', ""),
+                        premultiplied,
+                        Vec::new(),
+                    ));
                 }
                 "region" => {
                     let region_name = String::from(elements[1]).replace('"', "");
+                    //the flips are a single word at the end of the line
+                    let mut elements = elements;
+                    let region_flip = match elements.last() {
+                        Some(x) if x.starts_with("flip:") => {
+                            let flips = elements.pop().unwrap().trim_start_matches("flip:");
+                            Some(atlas::AtlasTextureFlip {
+                                flip_h: flips.contains('h'),
+                                flip_v: flips.contains('v'),
+                                flip_d: flips.contains('d'),
+                            })
+                        }
+                        _ => None,
+                    };
                     let region_values = elements
                         .iter()
                         .skip(2) //'region' and name
@@ -90,6 +124,21 @@ impl AtlasFormatter for TextFormatter {
 
                     let mut region_values: Vec<u32> =
                         region_values.into_iter().map(Option::unwrap).collect();
+                    //the trim values come last, and there are four of them
+                    let region_trim = if region_values.len() == 8 || region_values.len() == 11 {
+                        let source_height = region_values.pop().unwrap();
+                        let source_width = region_values.pop().unwrap();
+                        let offset_y = region_values.pop().unwrap();
+                        let offset_x = region_values.pop().unwrap();
+                        Some(atlas::AtlasTextureTrim {
+                            offset_x,
+                            offset_y,
+                            source_width,
+                            source_height,
+                        })
+                    } else {
+                        None
+                    };
                     let region_extras = if region_values.len() == 7 {
                         let original_height = region_values.pop().unwrap();
                         let original_width = region_values.pop().unwrap();
@@ -115,12 +164,14 @@ impl AtlasFormatter for TextFormatter {
                             width,
                             height,
                             extra: region_extras,
+                            trim: region_trim,
+                            flip: region_flip,
                         }
                     };
 
                     let current_page = result.last_mut();
                     match current_page {
-                        Some(page) => page.1.push(SourceTexture::from(region_info)),
+                        Some(page) => page.2.push(SourceTexture::from(region_info)),
                         None => {
                             //it's an error if there's no current page
                             return None;
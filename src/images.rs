@@ -1,8 +1,8 @@
-use std::{fs, io::BufWriter, path::Path, sync::mpsc::Sender};
+use std::{cmp, fs, io::BufWriter, path::Path, sync::mpsc::Sender};
 
 use image::{GenericImage, GenericImageView, ImageEncoder, Rgba};
 
-use crate::{packing::TexturePage, sources::SourceTexture, utils};
+use crate::{packing::TexturePage, rectangle::Rect, sources::SourceTexture, utils};
 
 fn save_image_to_disk<P: AsRef<Path>>(
     image: &image::RgbaImage, path: P,
@@ -61,29 +61,161 @@ pub fn generate_lut<P: AsRef<Path>>(
     save_image_to_disk(&canvas, &destination)
 }
 
+/// Changes made to the pixels of a page once all the textures are in it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageFilters {
+    pub bleed: bool,
+    pub premultiply: bool,
+}
+
 pub fn generate_image<P: AsRef<Path>>(
-    page: TexturePage, destination: P,
+    page: TexturePage, destination: P, filters: PageFilters,
 ) -> utils::GeneralResult<()> {
-    let (w, h) = match page.size {
-        Some((w, h)) => (w, h),
-        None => page.packed_bounds(),
-    };
+    let (w, h) = page.dimensions();
+    let extrusion = page.extrusion();
     let mut canvas = image::RgbaImage::new(w, h);
     for e in page.textures.into_iter().filter(|x| x.replica_of.is_none()) {
         let packing = e.packing.unwrap();
         let mut source = image::open(&e.path)?;
+        if let Some(trim) = e.trim {
+            let r = trim.rect;
+            source = source.crop_imm(r.x, r.y, r.width, r.height);
+        }
         if packing.rotated {
             source = image::DynamicImage::from(image::imageops::rotate90(&source));
         }
         canvas.copy_from(&source, packing.position.x, packing.position.y)?;
+        if extrusion > 0 {
+            extrude_edges(&mut canvas, &packing.position, extrusion);
+        }
+    }
+    if filters.bleed {
+        bleed_alpha(&mut canvas);
+    }
+    if filters.premultiply {
+        premultiply_alpha(&mut canvas);
     }
     save_image_to_disk(&canvas, &destination)
 }
 
+/// Creates an image out of other images, each at the given position
+pub fn compose_image<P: AsRef<Path>>(
+    (w, h): (u32, u32), parts: &[(&image::RgbaImage, u32, u32)], destination: P,
+) -> utils::GeneralResult<()> {
+    let mut canvas = image::RgbaImage::new(w, h);
+    for (part, x, y) in parts.iter() {
+        canvas.copy_from(*part, *x, *y)?;
+    }
+    save_image_to_disk(&canvas, &destination)
+}
+
+pub fn bleed_image<P: AsRef<Path>>((src, dst): (P, P)) -> utils::GeneralResult<()> {
+    let mut image = image::open(&src)?.into_rgba8();
+    bleed_alpha(&mut image);
+    save_image_to_disk(&image, &dst)
+}
+
+/// Fills the color of fully transparent pixels with the average of their nearest
+/// visible neighbours, spreading outwards from the visible ones. alpha is untouched
+fn bleed_alpha(image: &mut image::RgbaImage) {
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let mut filled: Vec<bool> = image.pixels().map(|p| p.0[3] > 0).collect();
+    let mut queued = filled.clone();
+    let neighbours = |i: usize| {
+        let (x, y) = ((i % w) as i64, (i / w) as i64);
+        (-1..=1)
+            .flat_map(move |dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+            .filter(move |&(nx, ny)| {
+                (nx, ny) != (x, y) && nx >= 0 && ny >= 0 && nx < w as i64 && ny < h as i64
+            })
+            .map(move |(nx, ny)| ny as usize * w + nx as usize)
+    };
+    //each wave is made of the transparent pixels next to the previous one,
+    //starting from the visible pixels
+    let mut wave: Vec<usize> = (0..(w * h)).filter(|&i| filled[i]).collect();
+    loop {
+        let mut next = Vec::new();
+        for &i in wave.iter() {
+            for n in neighbours(i) {
+                if !queued[n] {
+                    queued[n] = true;
+                    next.push(n);
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        //every pixel in the wave takes the colors filled in before it
+        let colors: Vec<[u8; 3]> = next
+            .iter()
+            .map(|&i| {
+                let (mut sum, mut count) = ([0u32; 3], 0);
+                for n in neighbours(i).filter(|&n| filled[n]) {
+                    let p = image.get_pixel((n % w) as u32, (n / w) as u32).0;
+                    (0..3).for_each(|c| sum[c] += p[c] as u32);
+                    count += 1;
+                }
+                sum.map(|x| (x / count) as u8)
+            })
+            .collect();
+        for (&i, color) in next.iter().zip(colors) {
+            let p = image.get_pixel_mut((i % w) as u32, (i / w) as u32);
+            p.0[..3].copy_from_slice(&color);
+            filled[i] = true;
+        }
+        wave = next;
+    }
+}
+
+fn premultiply_alpha(image: &mut image::RgbaImage) {
+    for p in image.pixels_mut() {
+        let a = p.0[3] as u32;
+        for c in p.0[..3].iter_mut() {
+            *c = ((*c as u32 * a + 127) / 255) as u8;
+        }
+    }
+}
+
+fn unpremultiply_alpha(image: &mut image::RgbaImage) {
+    for p in image.pixels_mut().filter(|p| p.0[3] > 0) {
+        let a = p.0[3] as u32;
+        for c in p.0[..3].iter_mut() {
+            *c = cmp::min((*c as u32 * 255 + a / 2) / a, 255) as u8;
+        }
+    }
+}
+
+/// Repeats the outermost pixels of the area R of the canvas N times around it
+fn extrude_edges(canvas: &mut image::RgbaImage, r: &Rect, n: u32) {
+    let (right, bottom) = (r.x + r.width - 1, r.y + r.height - 1);
+    for y in (r.y - n)..(bottom + n + 1) {
+        //inside the area, only the columns to the left and right need to be filled
+        let columns: Vec<u32> = match y >= r.y && y <= bottom {
+            true => ((r.x - n)..r.x)
+                .chain((right + 1)..(right + n + 1))
+                .collect(),
+            false => ((r.x - n)..(right + n + 1)).collect(),
+        };
+        for x in columns {
+            //the closest pixel inside the area
+            let pixel = *canvas.get_pixel(x.clamp(r.x, right), y.clamp(r.y, bottom));
+            canvas.put_pixel(x, y, pixel);
+        }
+    }
+}
+
 pub fn unpack_page<P: AsRef<Path>>(
-    (src, dst): (P, P), entries: &[SourceTexture], progress: Option<&Sender<u64>>,
+    (src, dst): (P, P), entries: &[SourceTexture], (premultiplied, premultiply): (bool, bool),
+    progress: Option<&Sender<u64>>,
 ) -> utils::GeneralResult<()> {
-    let source_image = image::open(&src)?;
+    let mut source_image = image::open(&src)?.into_rgba8();
+    //convert the page to the alpha mode the unpacked textures should have
+    match (premultiplied, premultiply) {
+        (true, false) => unpremultiply_alpha(&mut source_image),
+        (false, true) => premultiply_alpha(&mut source_image),
+        _ => (),
+    }
     for e in entries {
         let p = e.packing.clone().unwrap(); //safe call to unwrap
         let view = source_image
@@ -99,6 +231,16 @@ pub fn unpack_page<P: AsRef<Path>>(
         if p.rotated {
             canvas = image::imageops::rotate270(&canvas);
         }
+        //duplicates may be flipped copies of the packed texture
+        if let Some(flip) = e.flip.as_ref() {
+            canvas = flip.apply(&canvas);
+        }
+        //put trimmed textures back in their original canvas
+        if let Some(trim) = e.trim.as_ref() {
+            let mut original = image::RgbaImage::new(trim.source_size.0, trim.source_size.1);
+            original.copy_from(&canvas, trim.rect.x, trim.rect.y)?;
+            canvas = original;
+        }
         save_image_to_disk(&canvas, dst.as_ref().join(&e.path))?;
         if let Some(progress) = progress {
             progress.send(1)?;
@@ -1,4 +1,4 @@
-use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(name = env!("CARGO_PKG_NAME"))]
@@ -20,6 +20,10 @@ pub enum Commands {
     Arrange(ArrangeArguments),
     /// Generate a LUT texture with an optional palette
     Lut(LutArguments),
+    /// Fill the color of the transparent pixels of an image with their neighbours'
+    Bleed(BleedArguments),
+    /// Slice images into tiles, and pack the unique ones into a tileset
+    Tileset(TilesetArguments),
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
@@ -28,13 +32,69 @@ pub enum ArrangeDirection {
     Vertical,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum PackingEngine {
+    /// Maximal free rectangles
+    #[value(name = "maxrects")]
+    MaxRects,
+    /// Skyline (faster, slightly less efficient)
+    Skyline,
+    /// Guillotine cuts
+    Guillotine,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum GuillotineSplit {
+    /// Split along the shorter leftover axis
+    ShorterAxis,
+    /// Split along the longer leftover axis
+    LongerAxis,
+    /// Keep one large leftover rectangle at the expense of the other
+    MinArea,
+    /// Keep both leftover rectangles as even-sized as possible
+    MaxArea,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum SkylineFit {
+    /// Lowest top edge, then leftmost position
+    BottomLeft,
+    /// Least area wasted below the texture
+    MinWaste,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum PlacementMethod {
+    /// Shortest distance from the origin
+    Distance,
+    /// Smallest free area
+    Area,
+    /// Best short side fit
+    #[value(name = "bssf")]
+    ShortSideFit,
+    /// Best long side fit
+    #[value(name = "blsf")]
+    LongSideFit,
+    /// Lowest top edge, then leftmost position
+    BottomLeft,
+    /// Largest perimeter touching other textures or the page edges
+    ContactPoint,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum OutputFormat {
     Json,
     Text,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum QueryFormat {
+    Json,
+    Csv,
+}
+
 #[derive(Args, Debug, Clone)]
+#[command(group(ArgGroup::new("searching").args(["search", "search_iterations"]).multiple(true)))]
 pub struct PackArguments {
     /// Files or directories to be used as sources for the texture atlas
     #[arg(required = true)]
@@ -48,6 +108,18 @@ pub struct PackArguments {
     /// Space between the textures, in pixels
     #[arg(short = 's')]
     pub spacing: Option<u32>,
+    /// Repeat the edge pixels of each texture around it, N times
+    #[arg(long = "extrude", value_name = "N")]
+    pub extrude: Option<u32>,
+    /// Transparent pixels around every side of each texture
+    #[arg(long = "padding", value_name = "N")]
+    pub padding: Option<u32>,
+    /// Transparent pixels between the textures and the page edges
+    #[arg(long = "border", value_name = "N")]
+    pub border: Option<u32>,
+    /// Place the textures at, and round their sizes up to, multiples of N pixels
+    #[arg(long = "align", value_name = "N")]
+    pub align: Option<u32>,
     /// Use a fixed size for the texture pages
     #[arg(short = 'p')]
     pub page_size: Option<String>,
@@ -58,20 +130,105 @@ pub struct PackArguments {
     #[arg(short = 'q')]
     pub quiet: bool,
     /// Pack rectangles by area instead of distance
-    #[arg(long = "area")]
+    #[arg(long = "area", conflicts_with = "method")]
     pub pack_by_area: bool,
+    /// Heuristic used to choose a position for each texture
+    #[arg(long = "method")]
+    pub method: Option<PlacementMethod>,
+    /// Bin packing algorithm
+    #[arg(long = "engine")]
+    pub engine: Option<PackingEngine>,
+    /// Heuristic used by the skyline engine
+    #[arg(long = "skyline-fit")]
+    pub skyline_fit: Option<SkylineFit>,
+    /// Split rule used by the guillotine engine
+    #[arg(long = "split")]
+    pub split: Option<GuillotineSplit>,
+    /// Merge adjacent free rectangles (guillotine engine)
+    #[arg(long = "merge")]
+    pub merge: bool,
     /// Sort images by short side instead of long
     #[arg(long = "short")]
     pub short_side_sort: bool,
     /// Allow 90-degree rotation for more efficient packing
     #[arg(long = "rotate")]
     pub rotate: bool,
+    /// Always pack the texture that fits best next, instead of following the sorted order
+    #[arg(long = "global")]
+    pub global_best_fit: bool,
     /// Generate texture with power-of-two dimensions
     #[arg(long = "po2")]
     pub power_of_two: bool,
+    /// Spend this many seconds searching for a better packing order
+    #[arg(long = "search", value_name = "SECONDS")]
+    pub search: Option<f64>,
+    /// Stop searching after trying this many packing orders, so the result can be reproduced
+    #[arg(long = "search-iterations", value_name = "N")]
+    pub search_iterations: Option<u64>,
+    /// Seed for the packing order search
+    #[arg(long = "seed", requires = "searching")]
+    pub seed: Option<u64>,
+    /// Repack the last page into the smallest size that fits its textures
+    #[arg(long = "shrink-last", requires = "page_size")]
+    pub shrink_last_page: bool,
+    /// Try several page sizes and keep the one with the smallest area
+    #[arg(long = "optimize-size", conflicts_with = "page_size")]
+    pub optimize_size: bool,
+    /// Only consider square pages (see --optimize-size)
+    #[arg(long = "square", requires = "optimize_size")]
+    pub square: bool,
+    /// Largest size for dynamic pages, more pages are generated if needed
+    #[arg(long = "max-size", value_name = "WxH", conflicts_with = "page_size")]
+    pub max_size: Option<String>,
     /// Don't merge duplicate images in the output
     #[arg(long = "no-dedup")]
     pub include_duplicates: bool,
+    /// Also treat flipped and rotated copies of a texture as duplicates
+    #[arg(long = "dedup-flips", conflicts_with = "include_duplicates")]
+    pub dedup_flips: bool,
+    /// Crop the transparent borders of the images before packing
+    #[arg(long = "trim")]
+    pub trim: bool,
+    /// Pixels with this alpha value or lower are considered transparent when trimming
+    #[arg(long = "trim-threshold", value_name = "ALPHA", requires = "trim")]
+    pub trim_threshold: Option<u8>,
+    /// Fill the color of transparent pixels with the color of the nearest textures
+    #[arg(long = "bleed", conflicts_with = "premultiply")]
+    pub bleed: bool,
+    /// Multiply the color of every pixel by its alpha
+    #[arg(long = "premultiply")]
+    pub premultiply: bool,
+    /// Keep the textures of a previous description where they were, and pack the new ones around them
+    #[arg(
+        long = "previous",
+        value_name = "FILE",
+        conflicts_with_all = ["searching", "optimize_size", "shrink_last_page"]
+    )]
+    pub previous: Option<String>,
+    /// Pack a texture at a fixed position, in the first page or the given one
+    #[arg(
+        long = "pin",
+        value_name = "NAME=X,Y[,PAGE]",
+        conflicts_with_all = ["searching", "optimize_size", "shrink_last_page"]
+    )]
+    pub pin: Vec<String>,
+    /// JSON file with a list of textures to pack at fixed positions (see --pin)
+    #[arg(
+        long = "pins",
+        value_name = "FILE",
+        conflicts_with_all = ["searching", "optimize_size", "shrink_last_page"]
+    )]
+    pub pins: Option<String>,
+    /// Try the same settings as 'query' and keep the best result
+    #[arg(
+        long = "auto",
+        conflicts_with_all = [
+            "pack_by_area", "method", "engine", "skyline_fit", "split",
+            "merge", "short_side_sort", "global_best_fit", "searching", "previous",
+            "pin", "pins",
+        ]
+    )]
+    pub auto: bool,
 }
 
 #[derive(Args, Debug)]
@@ -88,6 +245,9 @@ pub struct UnpackArguments {
     /// Quiet mode
     #[arg(short = 'q')]
     pub quiet: bool,
+    /// Save the textures with their color multiplied by their alpha
+    #[arg(long = "premultiply")]
+    pub premultiply: bool,
 }
 
 #[derive(Args, Debug)]
@@ -95,15 +255,24 @@ pub struct QueryArguments {
     /// Files or directories to be used as sources for the query
     #[arg(required = true)]
     pub sources: Vec<String>,
-    /// Space between the textures, in pixels
-    #[arg(short = 's')]
-    pub spacing: Option<u32>,
-    /// Use a fixed size for the texture pages
-    #[arg(short = 'p')]
-    pub page_size: Option<String>,
+    /// Space between the textures, in pixels (several values can be separated by commas)
+    #[arg(short = 's', value_delimiter = ',')]
+    pub spacing: Vec<u32>,
+    /// Use a fixed size for the texture pages (several sizes can be separated by commas)
+    #[arg(short = 'p', value_delimiter = ',')]
+    pub page_size: Vec<String>,
+    /// Generate texture with power-of-two dimensions
+    #[arg(long = "po2")]
+    pub power_of_two: bool,
+    /// Find the smallest page size that fits the sources in this many pages
+    #[arg(long = "pages")]
+    pub pages: Option<usize>,
     /// Don't merge duplicate images in the output
     #[arg(long = "no-dedup")]
     pub include_duplicates: bool,
+    /// Print the results in a machine-readable format instead of a table
+    #[arg(long = "format")]
+    pub format: Option<QueryFormat>,
 }
 
 #[derive(Args, Debug)]
@@ -123,6 +292,12 @@ pub struct ArrangeArguments {
     /// Arrange images horizontally or vertically
     #[arg(short = 'd')]
     pub direction: Option<ArrangeDirection>,
+    /// Fill the color of transparent pixels with the color of the nearest textures
+    #[arg(long = "bleed", conflicts_with = "premultiply")]
+    pub bleed: bool,
+    /// Multiply the color of every pixel by its alpha
+    #[arg(long = "premultiply")]
+    pub premultiply: bool,
     /// Quiet mode
     #[arg(short = 'q')]
     pub quiet: bool,
@@ -152,3 +327,41 @@ pub struct LutArguments {
     #[arg(short = 'q')]
     pub quiet: bool,
 }
+
+#[derive(Args, Debug, Clone)]
+pub struct BleedArguments {
+    /// Image to be processed
+    #[arg(required = true)]
+    pub source: String,
+    /// Filename for the processed image
+    #[arg(required = true)]
+    pub output: String,
+    /// Overwrite existing files
+    #[arg(short = 'o')]
+    pub overwrite: bool,
+    /// Quiet mode
+    #[arg(short = 'q')]
+    pub quiet: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TilesetArguments {
+    /// Size of the tiles in WxH format, e.g. 16x16
+    #[arg(required = true)]
+    pub tile_size: String,
+    /// Files or directories to be sliced into tiles
+    #[arg(required = true)]
+    pub sources: Vec<String>,
+    /// File name for the generated files (e.g. 'foo' will generate 'foo.png' and 'foo.json')
+    #[arg(required = true)]
+    pub output: String,
+    /// Also treat flipped and rotated copies of a tile as duplicates
+    #[arg(long = "flips")]
+    pub flips: bool,
+    /// Overwrite existing files
+    #[arg(short = 'o')]
+    pub overwrite: bool,
+    /// Quiet mode
+    #[arg(short = 'q')]
+    pub quiet: bool,
+}
@@ -21,9 +21,11 @@ fn main() {
     let quiet_mode = match cmd.command {
         Pack(ref args) => args.quiet,
         Unpack(ref args) => args.quiet,
-        Query(_) => false,
+        Query(ref args) => args.format.is_some(),
         Arrange(ref args) => args.quiet,
         Lut(ref args) => args.quiet,
+        Bleed(ref args) => args.quiet,
+        Tileset(ref args) => args.quiet,
     };
 
     let mut log = match quiet_mode {
@@ -37,6 +39,8 @@ fn main() {
         Query(args) => commands::query(&args, &mut log),
         Arrange(args) => commands::arrange(&args, &mut log),
         Lut(args) => commands::lut(&args, &mut log),
+        Bleed(args) => commands::bleed(&args, &mut log),
+        Tileset(args) => commands::tileset(&args, &mut log),
     } {
         exit_with_error(&mut log, msg);
     }
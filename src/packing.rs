@@ -1,4 +1,10 @@
-use std::{cmp, fmt, mem, sync::mpsc};
+use std::{
+    cmp,
+    collections::{HashMap, HashSet},
+    fmt, mem,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
 
 use super::rectangle::Rect;
 use crate::{
@@ -9,11 +15,20 @@ use crate::{
 
 const MAX_DIMENSIONS: u32 = 65535;
 const MAX_SPACING: u32 = 1024;
+const GLOBAL_SHRINK_STEPS: usize = 16;
+const SEARCH_TEMPERATURE: f64 = 0.01;
+const SIZE_SEARCH_RATIOS: i32 = 8;
+//textures can only be kept in, or pinned to, pages up to this index
+const MAX_RESERVED_PAGE: usize = 1023;
 
 #[derive(Debug, Clone)]
 pub enum PackingMethod {
     Distance,
     Area,
+    ShortSideFit,
+    LongSideFit,
+    BottomLeft,
+    ContactPoint,
     Rows,
     Columns,
 }
@@ -22,16 +37,79 @@ impl fmt::Display for PackingMethod {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{self:?}") }
 }
 
+impl From<interface::PlacementMethod> for PackingMethod {
+    fn from(method: interface::PlacementMethod) -> Self {
+        use interface::PlacementMethod;
+        match method {
+            PlacementMethod::Distance => PackingMethod::Distance,
+            PlacementMethod::Area => PackingMethod::Area,
+            PlacementMethod::ShortSideFit => PackingMethod::ShortSideFit,
+            PlacementMethod::LongSideFit => PackingMethod::LongSideFit,
+            PlacementMethod::BottomLeft => PackingMethod::BottomLeft,
+            PlacementMethod::ContactPoint => PackingMethod::ContactPoint,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PackingAlgorithm {
+    MaxRects,
+    Skyline(SkylineMethod),
+    Guillotine(GuillotineSettings),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SkylineMethod {
+    BottomLeft,
+    MinWaste,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GuillotineSettings {
+    pub split: SplitRule,
+    pub merge: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SplitRule {
+    ShorterLeftoverAxis,
+    LongerLeftoverAxis,
+    MinArea,
+    MaxArea,
+}
+
 #[derive(Debug, Clone)]
 pub struct PackingSettings {
+    pub algorithm: PackingAlgorithm,
     pub method: PackingMethod,
     pub spacing: u32,
+    pub extrude: u32,
+    pub padding: u32,
+    pub border: u32,
+    pub align: u32,
     pub rotation: bool,
+    pub global: bool,
+    pub power_of_two: bool,
     pub page_size: Option<(u32, u32)>,
+    pub shrink_last_page: bool,
+    pub search: Option<SearchSettings>,
+    pub optimize_size: Option<PageShape>,
+    pub max_size: Option<(u32, u32)>,
     pub arrange: Option<ArrangeSettings>,
     pub source_treatment: Option<SourceTreatment>,
 }
 
+impl PackingSettings {
+    /// The space a texture takes up in a page
+    fn footprint(&self, dimensions: (u32, u32)) -> Rect { Gutter::new(self).footprint(dimensions) }
+
+    /// The smallest page that can fit a texture
+    pub fn minimum_page_size(&self, dimensions: (u32, u32)) -> (u32, u32) {
+        let r = self.footprint(dimensions);
+        (r.width + self.border * 2, r.height + self.border * 2)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum SortingMethod {
     ShortSide,
@@ -42,6 +120,10 @@ pub enum SortingMethod {
 pub struct SourceTreatment {
     pub sorting: SortingMethod,
     pub deduplicate: bool,
+    //also treat flipped and rotated copies as duplicates
+    pub deduplicate_flips: bool,
+    //alpha threshold for trimming transparent borders
+    pub trim: Option<u8>,
 }
 
 impl Default for SourceTreatment {
@@ -49,10 +131,26 @@ impl Default for SourceTreatment {
         Self {
             sorting: SortingMethod::LongSide,
             deduplicate: true,
+            deduplicate_flips: false,
+            trim: None,
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct SearchSettings {
+    //the search stops at whichever of these limits comes first
+    pub budget: Option<Duration>,
+    pub iterations: Option<u64>,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageShape {
+    Any,
+    Square,
+}
+
 #[derive(Debug, Clone)]
 pub struct ArrangeSettings {
     layout: (u32, u32),
@@ -64,6 +162,95 @@ pub struct TexturePage {
     pub textures: Vec<SourceTexture>,
     pub size: Option<(u32, u32)>,
     free_slots: Vec<Rect>,
+    skyline: Vec<SkylineSegment>,
+    gutter: Gutter,
+}
+
+/// The pixels kept around every texture in a page, and around the page itself.
+/// textures are packed in the area inside the border, and their positions are
+/// translated to page coordinates afterwards
+#[derive(Debug, Clone, Copy)]
+struct Gutter {
+    //copies of the texture's edges, on every side
+    extrude: u32,
+    //transparent pixels, on every side
+    padding: u32,
+    //transparent pixels, to the right and below
+    spacing: u32,
+    //transparent pixels along the page edges
+    border: u32,
+    //textures start at, and take up, multiples of this
+    align: u32,
+}
+
+impl Gutter {
+    fn new(settings: &PackingSettings) -> Self {
+        Gutter {
+            extrude: settings.extrude,
+            padding: settings.padding,
+            spacing: settings.spacing,
+            border: settings.border,
+            align: settings.align,
+        }
+    }
+
+    /// How far a texture is from the corner of the space it takes up. the space
+    /// itself is always aligned, so this aligns the texture in page coordinates
+    fn offset(&self) -> u32 {
+        let leading = self.border + self.extrude + self.padding;
+        leading.next_multiple_of(self.align) - self.border
+    }
+
+    /// The space taken up by a texture of the given dimensions
+    fn footprint(&self, (w, h): (u32, u32)) -> Rect {
+        let trailing = self.offset() + self.extrude + self.padding + self.spacing;
+        Rect::new(
+            0,
+            0,
+            (w + trailing).next_multiple_of(self.align),
+            (h + trailing).next_multiple_of(self.align),
+        )
+    }
+
+    /// The space taken up by a texture packed at the given
+    /// position, relative to the area inside the border
+    fn around(&self, r: &Rect) -> Rect {
+        let offset = self.offset();
+        let mut footprint = self.footprint((r.width, r.height));
+        footprint.place_at(r.x - offset - self.border, r.y - offset - self.border);
+        footprint
+    }
+
+    /// A texture of the given dimensions inside the space R, in page coordinates
+    fn within(&self, r: &Rect, (w, h): (u32, u32)) -> Rect {
+        let offset = self.offset();
+        Rect::new(r.x + offset + self.border, r.y + offset + self.border, w, h)
+    }
+
+    /// The area inside the border of a page of the given size
+    fn inside(&self, (w, h): (u32, u32)) -> (u32, u32) {
+        (
+            w.saturating_sub(self.border * 2),
+            h.saturating_sub(self.border * 2),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SkylineSegment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+#[derive(Debug)]
+struct Placement {
+    //index of the free slot or skyline segment used
+    slot: usize,
+    //the rectangle, rotated and moved to its position
+    rect: Rect,
+    rotated: bool,
+    score: (i64, i64),
 }
 
 #[derive(Debug)]
@@ -72,6 +259,8 @@ pub struct TexturePacker {
     pub pages: Vec<TexturePage>,
     pub settings: PackingSettings,
     sources: Vec<SourceTexture>,
+    //whether some textures were placed before packing, and can't be moved
+    reserved: bool,
 }
 
 impl TexturePacker {
@@ -81,11 +270,51 @@ impl TexturePacker {
         TexturePacker {
             label: String::from(label),
             sources: sources.into_iter().collect(),
-            pages: vec![TexturePage::new(label, settings.page_size)],
+            pages: vec![TexturePage::new(label, settings.page_size, &settings)],
             settings,
+            reserved: false,
         }
     }
 
+    /// Puts the sources that already have a position back where they were, in the
+    /// page with the given index, before anything else is packed. the ones that
+    /// can't go there anymore are packed along with the rest
+    pub fn keep_positions(&mut self, positions: &HashMap<String, (usize, PackingData)>) -> usize {
+        let mut kept = 0;
+        for mut texture in mem::take(&mut self.sources).into_iter() {
+            let (w, h) = (texture.dimensions.width, texture.dimensions.height);
+            let position = match texture.replica_of {
+                Some(_) => None,
+                None => positions.get(&texture.name),
+            };
+            //the texture must have the same size, and only be rotated if that's allowed
+            if let Some((page, packing)) = position
+                && *page <= MAX_RESERVED_PAGE
+                && (self.settings.rotation || !packing.rotated)
+                && (packing.position.width, packing.position.height)
+                    == if packing.rotated { (h, w) } else { (w, h) }
+            {
+                while self.pages.len() <= *page {
+                    self.add_page();
+                }
+                if self.pages[*page].reserve(&packing.position, &self.settings) {
+                    texture.packing = Some(packing.clone());
+                    self.pages[*page].textures.push(texture);
+                    kept += 1;
+                    continue;
+                }
+            }
+            self.sources.push(texture);
+        }
+        if kept > 0 {
+            self.reserved = true;
+            for page in self.pages.iter_mut().filter(|x| x.size.is_none()) {
+                page.reclaim_gaps(&self.settings);
+            }
+        }
+        kept
+    }
+
     pub fn count(&self) -> usize {
         self.sources
             .len()
@@ -106,9 +335,8 @@ impl TexturePacker {
     }
 
     pub fn page_size(&self) -> (u32, u32) {
-        match self.settings.page_size {
-            Some((w, h)) => (w, h),
-            None if !self.pages.is_empty() => self.pages[0].packed_bounds(),
+        match self.pages.first() {
+            Some(page) => page.dimensions(),
             None => (0, 0),
         }
     }
@@ -129,22 +357,127 @@ impl TexturePacker {
     }
 
     pub fn total_packed_area(&self) -> u64 {
-        match self.settings.page_size {
-            Some((w, h)) => ((self.pages.len() as u32) * w * h) as u64,
+        self.pages
+            .iter()
+            .map(|x| {
+                let (w, h) = x.dimensions();
+                (w as u64) * (h as u64)
+            })
+            .sum()
+    }
+
+    pub fn efficiency(&self) -> f64 {
+        (self.total_source_area() as f64) / (self.total_packed_area() as f64) * 100.0
+    }
+
+    /// The amount of progress that will be reported while packing
+    pub fn workload(&self) -> u64 {
+        match &self.settings.search {
+            Some(search) => match (search.iterations, search.budget) {
+                (Some(iterations), _) => iterations,
+                (None, Some(budget)) => budget.as_millis() as u64,
+                (None, None) => 0,
+            },
+            //textures already placed in the pages don't report any progress
             None => {
-                let bounds = self.pages[0].packed_bounds();
-                (bounds.0 * bounds.1) as u64
+                let placed: usize = self.pages.iter().map(|x| x.textures.len()).sum();
+                self.count()
+                    .saturating_sub(self.duplicates())
+                    .saturating_sub(placed) as u64
             }
         }
     }
 
-    pub fn efficiency(&self) -> f64 {
-        (self.total_source_area() as f64) / (self.total_packed_area() as f64) * 100.0
+    fn fit_power_of_two(&mut self) {
+        //square pages stay square, with both sides the same power of two
+        let square = matches!(self.settings.optimize_size, Some(PageShape::Square));
+        for idx in 0..self.pages.len() {
+            let (w, h) = self.pages[idx].packed_bounds();
+            let mut best = match square {
+                true => {
+                    let side = cmp::max(w, h).next_power_of_two();
+                    (side, side)
+                }
+                false => (w.next_power_of_two(), h.next_power_of_two()),
+            };
+            //reserved textures can't be moved to a smaller page
+            if self.reserved {
+                self.pages[idx].size = Some(best);
+                continue;
+            }
+            //the smallest dimensions any page must have to fit the largest texture
+            let minimum = self.pages[idx]
+                .textures
+                .iter()
+                .map(|x| {
+                    let (w, h) = (x.dimensions.width, x.dimensions.height);
+                    match self.settings.rotation {
+                        true => (cmp::min(w, h), cmp::min(w, h)),
+                        false => (w, h),
+                    }
+                })
+                .fold((1, 1), |acc, x| {
+                    (cmp::max(acc.0, x.0), cmp::max(acc.1, x.1))
+                });
+            let source_area: u64 = self.pages[idx]
+                .textures
+                .iter()
+                .map(|x| x.dimensions.area() as u64)
+                .sum();
+            //build a list of all the power-of-two sizes smaller than the
+            //current one that could possibly contain the page's textures
+            let mut candidates = Vec::new();
+            let mut cw = minimum.0.next_power_of_two();
+            while cw <= best.0 {
+                let mut ch = minimum.1.next_power_of_two();
+                while ch <= best.1 {
+                    let area = (cw as u64) * (ch as u64);
+                    if area >= source_area
+                        && area < (best.0 as u64) * (best.1 as u64)
+                        && (!square || cw == ch)
+                    {
+                        candidates.push((cw, ch));
+                    }
+                    ch *= 2;
+                }
+                cw *= 2;
+            }
+            //try the smallest and most square sizes first
+            candidates.sort_by_key(|&(cw, ch)| ((cw as u64) * (ch as u64), cw.abs_diff(ch)));
+            //and keep the first one that can fit every texture
+            let mut repacked = None;
+            for size in candidates {
+                if let Some(page) = self.repack_page(&self.pages[idx], size) {
+                    best = size;
+                    repacked = Some(page);
+                    break;
+                }
+            }
+            if let Some(page) = repacked {
+                self.pages[idx] = page;
+            }
+            self.pages[idx].size = Some(best);
+        }
+    }
+
+    fn repack_page(&self, page: &TexturePage, size: (u32, u32)) -> Option<TexturePage> {
+        let mut r = TexturePage::new(&page.name, Some(size), &self.settings);
+        for texture in page.textures.iter() {
+            let dimensions = (texture.dimensions.width, texture.dimensions.height);
+            let packing = r.pack_rectangle(dimensions, &self.settings)?;
+            let mut texture = texture.clone();
+            texture.packing = Some(packing);
+            r.textures.push(texture);
+        }
+        Some(r)
     }
 
     fn add_page(&mut self) {
-        self.pages
-            .push(TexturePage::new(&self.label, self.settings.page_size));
+        self.pages.push(TexturePage::new(
+            &self.label,
+            self.settings.page_size,
+            &self.settings,
+        ));
     }
 
     fn adjust_page_names(&mut self) {
@@ -156,6 +489,120 @@ impl TexturePacker {
         }
     }
 
+    pub fn search_everything(
+        &mut self, progress: Option<mpsc::Sender<u64>>,
+    ) -> utils::GeneralResult<()> {
+        let search = self.settings.search.clone().unwrap();
+        //the last page is only shrunk once the search is over
+        let settings = PackingSettings {
+            search: None,
+            shrink_last_page: false,
+            ..self.settings.clone()
+        };
+        let sources = mem::take(&mut self.sources);
+        let mut random = utils::Random::new(search.seed);
+        //each candidate is an order for the sources,
+        //and whether each of them is rotated beforehand
+        let mut current: Vec<(usize, bool)> = (0..sources.len()).map(|x| (x, false)).collect();
+        //the sorted order is the starting point
+        let mut best = self.pack_candidate(&sources, &current, &settings)?;
+        let mut current_cost = search_cost(&best);
+        let start = Instant::now();
+        let (mut reported, mut iteration) = (0, 0);
+        loop {
+            let elapsed = start.elapsed();
+            //report the iterations or the time spent so far
+            if let Some(progress) = progress.as_ref() {
+                let done = match (search.iterations, search.budget) {
+                    (Some(_), _) => iteration,
+                    (None, Some(budget)) => cmp::min(elapsed, budget).as_millis() as u64,
+                    (None, None) => 0,
+                };
+                let _ = progress.send(done - reported);
+                reported = done;
+            }
+            let out_of_time = search.budget.is_some_and(|x| elapsed >= x);
+            let out_of_iterations = search.iterations.is_some_and(|x| iteration >= x);
+            if out_of_time || out_of_iterations || current.len() < 2 {
+                break;
+            }
+            iteration += 1;
+            //move a few textures around, and maybe rotate some of them
+            let mut candidate = current.clone();
+            for _ in 0..=random.below(3) {
+                let (a, b) = (random.below(candidate.len()), random.below(candidate.len()));
+                candidate.swap(a, b);
+                if settings.rotation && random.below(4) == 0 {
+                    candidate[a].1 = !candidate[a].1;
+                }
+            }
+            let packer = self.pack_candidate(&sources, &candidate, &settings)?;
+            let cost = search_cost(&packer);
+            //worse candidates are sometimes accepted too, less and less often as
+            //the search goes on, to escape local minima. with a limited number of
+            //iterations the clock is left out, so the same seed gives the same result
+            let progress = match (search.iterations, search.budget) {
+                (Some(iterations), _) => iteration as f64 / iterations as f64,
+                (None, Some(budget)) => elapsed.as_secs_f64() / budget.as_secs_f64(),
+                (None, None) => 1.0,
+            };
+            let temperature = SEARCH_TEMPERATURE * (1.0 - progress);
+            let accepted = cost <= current_cost || {
+                let (c, p) = match cost.0 == current_cost.0 {
+                    true => (cost.1, current_cost.1),
+                    false => (cost.0, current_cost.0),
+                };
+                let worsening = (c as f64 - p as f64) / cmp::max(p, 1) as f64;
+                random.unit() < (-worsening / temperature).exp()
+            };
+            if accepted {
+                current = candidate;
+                current_cost = cost;
+            }
+            if cost < search_cost(&best) {
+                best = packer;
+            }
+        }
+        self.pages = best.pages;
+        if self.settings.shrink_last_page {
+            self.shrink_last_page()?;
+        }
+        Ok(())
+    }
+
+    fn pack_candidate(
+        &self, sources: &[SourceTexture], order: &[(usize, bool)], settings: &PackingSettings,
+    ) -> utils::GeneralResult<TexturePacker> {
+        let candidate = order.iter().map(|&(idx, rotated)| {
+            let mut texture = sources[idx].clone();
+            if rotated && texture.replica_of.is_none() {
+                texture.dimensions.rotate();
+            }
+            texture
+        });
+        let mut packer = TexturePacker::new(&self.label, candidate, settings.clone());
+        packer.pack_everything(None)?;
+        //undo the rotations applied before packing
+        let rotated: HashSet<&str> = order
+            .iter()
+            .filter(|x| x.1 && sources[x.0].replica_of.is_none())
+            .map(|x| sources[x.0].name.as_str())
+            .collect();
+        for texture in packer.pages.iter_mut().flat_map(|x| x.textures.iter_mut()) {
+            let original = texture.replica_of.as_ref().unwrap_or(&texture.name);
+            if !rotated.contains(original.as_str()) {
+                continue;
+            }
+            if let Some(packing) = texture.packing.as_mut() {
+                packing.rotated = !packing.rotated;
+            }
+            if texture.replica_of.is_none() {
+                texture.dimensions.rotate();
+            }
+        }
+        Ok(packer)
+    }
+
     pub fn arrange_everything(
         &mut self, progress: Option<mpsc::Sender<u64>>,
     ) -> utils::GeneralResult<()> {
@@ -184,56 +631,37 @@ impl TexturePacker {
     pub fn pack_everything(
         &mut self, progress: Option<mpsc::Sender<u64>>,
     ) -> utils::GeneralResult<()> {
-        //unbox the sources container
-        let sources = mem::take(&mut self.sources);
-        let mut replicas = Vec::new();
-        //iterate over the source textures
-        for mut texture in sources.into_iter() {
-            match texture.replica_of {
-                //if this texture is not a duplicate
-                None => {
-                    //retrieve its dimensions
-                    let dimensions = (texture.dimensions.width, texture.dimensions.height);
-                    //find the first page where it can be packed
-                    let mut packing = self.pages.iter_mut().enumerate().find_map(|(i, x)| {
-                        x.pack_rectangle(dimensions, &self.settings).map(|p| (i, p))
-                    });
-                    //if the texture couldn't be packed in any page
-                    if packing.is_none() {
-                        //create a new page
-                        self.add_page();
-                        let last_page = self.pages.len() - 1;
-                        //and pack the texture in it
-                        if let Some(p) =
-                            self.pages[last_page].pack_rectangle(dimensions, &self.settings)
-                        {
-                            packing = Some((last_page, p));
-                        }
-                    }
-                    //report progress
-                    if let Some(progress) = progress.as_ref() {
-                        let _ = progress.send(1);
-                    }
-                    //at this point it's impossible for
-                    //the texture not to be packed
-                    if let Some((page_index, packing)) = packing {
-                        //add the packing data to the texture struct
-                        texture.packing = Some(packing);
-                        //and move the texture to the page
-                        self.pages[page_index].textures.push(texture);
-                    } else {
-                        //this should never happen, but just in case...
-                        return Err(format!("failed to pack texture '{}'.", texture.name).into());
-                    }
-                }
-                //if this texture is a duplicate of another
-                Some(_) => {
-                    //move it to the list of replicas
-                    replicas.push(texture);
+        //unbox the sources container, and set
+        //the duplicate textures aside for now
+        let (sources, mut replicas): (Vec<_>, Vec<_>) = mem::take(&mut self.sources)
+            .into_iter()
+            .partition(|x| x.replica_of.is_none());
+        match (self.settings.optimize_size, self.settings.global) {
+            //try several fixed page sizes and keep the smallest
+            (Some(shape), _) if !self.reserved => {
+                self.pack_smallest_page(sources, shape, progress.as_ref())?
+            }
+            //pick the best texture for each free position, pages with
+            //reserved textures are only filled around them
+            (_, true) => match (self.settings.page_size, self.reserved) {
+                (None, false) => self.pack_globally_dynamic(sources, progress.as_ref())?,
+                _ => self.pack_globally(sources, progress.as_ref())?,
+            },
+            //or pack the textures in their sorted order
+            (_, false) => {
+                for texture in sources.into_iter() {
+                    self.pack_texture(texture, progress.as_ref())?;
                 }
             }
         }
-
+        //dynamic pages must be expanded to power-of-two dimensions
+        if self.settings.power_of_two && self.settings.page_size.is_none() {
+            self.fit_power_of_two();
+        }
+        //the last fixed page may be made smaller
+        if self.settings.shrink_last_page && self.settings.page_size.is_some() && !self.reserved {
+            self.shrink_last_page()?;
+        }
         //iterate over all the duplicate textures
         for mut texture in replicas.drain(..) {
             //find the name of the original texture
@@ -251,130 +679,728 @@ impl TexturePacker {
                 }
             }
         }
+        //trailing pages that only held removed textures are left out,
+        //the others keep their place so the pages after them do too
+        if self.reserved {
+            while self.pages.last().is_some_and(|x| x.textures.is_empty()) {
+                self.pages.pop();
+            }
+            //empty dynamic pages in between get the smallest size an image can have
+            let side = cmp::max(self.settings.border * 2, 1).next_multiple_of(self.settings.align);
+            self.pages
+                .iter_mut()
+                .filter(|x| x.textures.is_empty() && x.size.is_none())
+                .for_each(|x| x.size = Some((side, side)));
+        }
         //fix the page names
         self.adjust_page_names();
         Ok(())
     }
+
+    fn pack_texture(
+        &mut self, mut texture: SourceTexture, progress: Option<&mpsc::Sender<u64>>,
+    ) -> utils::GeneralResult<()> {
+        //retrieve its dimensions
+        let dimensions = (texture.dimensions.width, texture.dimensions.height);
+        //find the first page where it can be packed
+        let mut packing = self
+            .pages
+            .iter_mut()
+            .enumerate()
+            .find_map(|(i, x)| x.pack_rectangle(dimensions, &self.settings).map(|p| (i, p)));
+        //if the texture couldn't be packed in any page
+        if packing.is_none() {
+            //create a new page
+            self.add_page();
+            let last_page = self.pages.len() - 1;
+            //and pack the texture in it
+            if let Some(p) = self.pages[last_page].pack_rectangle(dimensions, &self.settings) {
+                packing = Some((last_page, p));
+            }
+        }
+        //report progress
+        if let Some(progress) = progress {
+            let _ = progress.send(1);
+        }
+        //at this point it's impossible for
+        //the texture not to be packed
+        if let Some((page_index, packing)) = packing {
+            //add the packing data to the texture struct
+            texture.packing = Some(packing);
+            //and move the texture to the page
+            self.pages[page_index].textures.push(texture);
+            Ok(())
+        } else {
+            //this should never happen, but just in case...
+            Err(format!("failed to pack texture '{}'.", texture.name).into())
+        }
+    }
+
+    fn shrink_last_page(&mut self) -> utils::GeneralResult<()> {
+        let Some(page) = self.pages.last() else {
+            return Ok(());
+        };
+        //the textures in the last page are packed again into the smallest
+        //page that fits them, no larger than the fixed page size
+        let settings = PackingSettings {
+            page_size: None,
+            shrink_last_page: false,
+            search: None,
+            optimize_size: Some(PageShape::Any),
+            max_size: self.settings.page_size,
+            ..self.settings.clone()
+        };
+        let textures = page.textures.iter().cloned().map(|mut x| {
+            x.packing = None;
+            x
+        });
+        let mut packer = TexturePacker::new(&page.name, textures, settings);
+        packer.pack_everything(None)?;
+        if let (1, Some(mut shrunk)) = (packer.pages.len(), packer.pages.pop()) {
+            let (w, h) = shrunk.dimensions();
+            let (pw, ph) = page.dimensions();
+            if (w as u64) * (h as u64) < (pw as u64) * (ph as u64) {
+                shrunk.name = page.name.clone();
+                shrunk.size = Some((w, h));
+                *self.pages.last_mut().unwrap() = shrunk;
+            }
+        }
+        Ok(())
+    }
+
+    fn pack_smallest_page(
+        &mut self, sources: Vec<SourceTexture>, shape: PageShape,
+        progress: Option<&mpsc::Sender<u64>>,
+    ) -> utils::GeneralResult<()> {
+        let limits = self
+            .settings
+            .max_size
+            .unwrap_or((MAX_DIMENSIONS, MAX_DIMENSIONS));
+        let footprints: Vec<Rect> = sources
+            .iter()
+            .map(|x| {
+                let dimensions = (x.dimensions.width, x.dimensions.height);
+                self.settings.footprint(dimensions)
+            })
+            .collect();
+        //no page can be smaller than the sum of the footprints,
+        //or narrower or shorter than the largest of them
+        let area: u64 = footprints.iter().map(|x| x.area() as u64).sum();
+        let largest = footprints
+            .iter()
+            .map(|x| match self.settings.rotation {
+                true => (cmp::min(x.width, x.height), cmp::min(x.width, x.height)),
+                false => (x.width, x.height),
+            })
+            .fold((1, 1), |acc, x| {
+                (cmp::max(acc.0, x.0), cmp::max(acc.1, x.1))
+            });
+        //each attempt packs everything into a page of the given size,
+        //and only counts if it all fit on that single page
+        let settings = PackingSettings {
+            optimize_size: None,
+            power_of_two: false,
+            ..self.settings.clone()
+        };
+        let attempt = |size: (u32, u32)| {
+            let settings = PackingSettings {
+                page_size: Some(size),
+                ..settings.clone()
+            };
+            let mut packer = TexturePacker::new(&self.label, sources.clone(), settings);
+            match packer.pack_everything(None) {
+                Ok(_) if packer.pages.len() == 1 => Some(packer),
+                _ => None,
+            }
+        };
+        //the candidate aspect ratios (width over height) go from 1:4 to 4:1
+        let ratios: Vec<f64> = match shape {
+            PageShape::Square => vec![1.0],
+            PageShape::Any => (-SIZE_SEARCH_RATIOS..=SIZE_SEARCH_RATIOS)
+                .map(|x| 2f64.powf(x as f64 / (SIZE_SEARCH_RATIOS as f64 / 2.0)))
+                .collect(),
+        };
+        let align = self.settings.align;
+        let page = |height: u32, ratio: f64| {
+            let width = (height as f64 * ratio).round() as u32;
+            (
+                width.next_multiple_of(align),
+                height.next_multiple_of(align),
+            )
+        };
+        let mut best: Option<(TexturePacker, (u32, u32))> = None;
+        let mut best_cost = (u64::MAX, u64::MAX);
+        for ratio in ratios {
+            //the shortest page with this ratio that could possibly fit everything
+            let mut low = cmp::max(
+                ((area as f64 / ratio).sqrt().ceil() as u32).max(largest.1),
+                (largest.0 as f64 / ratio).ceil() as u32,
+            );
+            //and the tallest one that is allowed and smaller than the best so far
+            let mut high = cmp::min(limits.1, (limits.0 as f64 / ratio).floor() as u32);
+            if best.is_some() {
+                high = cmp::min(high, (best_cost.1 as f64 / ratio).sqrt().floor() as u32);
+            }
+            if low > high {
+                continue;
+            }
+            let Some(mut found) = attempt(page(high, ratio)) else {
+                continue;
+            };
+            //then find the smallest page that fits by bisection
+            while low < high {
+                let middle = low + (high - low) / 2;
+                match attempt(page(middle, ratio)) {
+                    Some(packer) => {
+                        high = middle;
+                        found = packer;
+                    }
+                    None => low = middle + 1,
+                }
+            }
+            //power-of-two pages are compared by their final size
+            let (w, h) = found.pages[0].packed_bounds();
+            let cost = match self.settings.power_of_two {
+                true => (
+                    (w.next_power_of_two() as u64) * (h.next_power_of_two() as u64),
+                    (w as u64) * (h as u64),
+                ),
+                false => ((w as u64) * (h as u64), (w as u64) * (h as u64)),
+            };
+            if cost < best_cost {
+                best = Some((found, page(high, ratio)));
+                best_cost = cost;
+            }
+        }
+        match best {
+            //if everything can't fit on a single page, spill into several
+            None if self.settings.max_size.is_some() => {
+                let mut packer = TexturePacker::new(&self.label, sources.clone(), settings);
+                packer.pack_everything(None)?;
+                self.pages = packer.pages;
+            }
+            Some((mut best, size)) => {
+                //square pages keep their size, the others shrink to fit
+                best.pages[0].size = match shape {
+                    PageShape::Square => Some(size),
+                    PageShape::Any => None,
+                };
+                self.pages = best.pages;
+            }
+            None => {
+                return Err(format!(
+                    "the textures don't fit in a single page of up to {}x{}.",
+                    limits.0, limits.1
+                )
+                .into());
+            }
+        }
+        if let Some(progress) = progress {
+            let _ = progress.send(sources.len() as u64);
+        }
+        Ok(())
+    }
+
+    fn pack_globally_dynamic(
+        &mut self, sources: Vec<SourceTexture>, progress: Option<&mpsc::Sender<u64>>,
+    ) -> utils::GeneralResult<()> {
+        //dynamic pages have no free space to choose from, so a first
+        //pass in sorted order finds out how large the page needs to be
+        let settings = PackingSettings {
+            global: false,
+            power_of_two: false,
+            ..self.settings.clone()
+        };
+        let mut best = TexturePacker::new(&self.label, sources.clone(), settings.clone());
+        best.pack_everything(None)?;
+        //if the textures spill over several pages, they are packed
+        //globally into pages of the maximum size, shrunk afterwards
+        if let (true, Some(limits)) = (best.pages.len() > 1, self.settings.max_size) {
+            let settings = PackingSettings {
+                page_size: Some(limits),
+                ..settings
+            };
+            let mut attempt = TexturePacker::new(&self.label, Vec::new(), settings);
+            attempt.pack_globally(sources.clone(), None)?;
+            attempt.pages.iter_mut().for_each(|x| x.size = None);
+            if (attempt.pages.len(), search_cost(&attempt)) < (best.pages.len(), search_cost(&best))
+            {
+                best = attempt;
+            }
+            self.pages = best.pages;
+            if let Some(progress) = progress {
+                let _ = progress.send(sources.len() as u64);
+            }
+            return Ok(());
+        }
+        //no page can be narrower or shorter than the largest texture needs
+        let least = sources
+            .iter()
+            .map(|x| {
+                let (w, h) = settings.minimum_page_size((x.dimensions.width, x.dimensions.height));
+                match settings.rotation {
+                    true => (cmp::min(w, h), cmp::min(w, h)),
+                    false => (w, h),
+                }
+            })
+            .fold((1, 1), |acc, x| {
+                (cmp::max(acc.0, x.0), cmp::max(acc.1, x.1))
+            });
+        //then the textures are packed globally in ever smaller
+        //pages, for as long as they still fit in a single one
+        let mut area = best.page_size();
+        for _ in 0..GLOBAL_SHRINK_STEPS {
+            let settings = PackingSettings {
+                page_size: Some(area),
+                ..settings.clone()
+            };
+            let mut attempt = TexturePacker::new(&self.label, Vec::new(), settings);
+            //an attempt that fails or spills over doesn't fit, the best so far is kept
+            match attempt.pack_globally(sources.clone(), None) {
+                Ok(_) if attempt.pages.len() == 1 => (),
+                _ => break,
+            }
+            let (w, h) = attempt.pages[0].packed_bounds();
+            if (w as u64) * (h as u64) < best.total_packed_area() {
+                attempt.pages[0].size = None;
+                best = attempt;
+            }
+            //shave a little off the longest side
+            area = match w >= h {
+                true => (w.saturating_sub(cmp::max(w / 50, 1)), h),
+                false => (w, h.saturating_sub(cmp::max(h / 50, 1))),
+            };
+            if area.0 < least.0 || area.1 < least.1 {
+                break;
+            }
+        }
+        self.pages = best.pages;
+        if let Some(progress) = progress {
+            let _ = progress.send(sources.len() as u64);
+        }
+        Ok(())
+    }
+
+    fn pack_globally(
+        &mut self, mut sources: Vec<SourceTexture>, progress: Option<&mpsc::Sender<u64>>,
+    ) -> utils::GeneralResult<()> {
+        while !sources.is_empty() {
+            //score every remaining texture in every page
+            let mut best: Option<(usize, usize, Placement)> = None;
+            for (idx, texture) in sources.iter().enumerate() {
+                let dimensions = (texture.dimensions.width, texture.dimensions.height);
+                let r = self.settings.footprint(dimensions);
+                for (page_index, page) in self.pages.iter().enumerate() {
+                    if let Some(p) = page.find_placement(&r, &self.settings)
+                        && best.as_ref().is_none_or(|x| p.score < x.2.score)
+                    {
+                        best = Some((idx, page_index, p));
+                    }
+                }
+            }
+            match best {
+                //place the best candidate where it scored best
+                Some((idx, page_index, placement)) => {
+                    let mut texture = sources.remove(idx);
+                    let dimensions = (texture.dimensions.width, texture.dimensions.height);
+                    let page = &mut self.pages[page_index];
+                    texture.packing =
+                        Some(page.apply_placement(placement, dimensions, &self.settings));
+                    page.textures.push(texture);
+                    if let Some(progress) = progress {
+                        let _ = progress.send(1);
+                    }
+                }
+                //if nothing fits anywhere, the next texture in order
+                //either grows the page or goes to a new one
+                None => self.pack_texture(sources.remove(0), progress)?,
+            }
+        }
+        Ok(())
+    }
 }
 
 impl TexturePage {
-    pub fn new(name: &str, size: Option<(u32, u32)>) -> Self {
+    pub fn new(name: &str, size: Option<(u32, u32)>, settings: &PackingSettings) -> Self {
+        let gutter = Gutter::new(settings);
+        let area = size.map(|x| gutter.inside(x));
         TexturePage {
             name: String::from(name),
             textures: Vec::new(),
             size,
-            free_slots: match size {
+            free_slots: match area {
                 Some((w, h)) => vec![Rect::new(0, 0, w, h)],
                 None => vec![],
             },
+            skyline: vec![SkylineSegment {
+                x: 0,
+                y: 0,
+                width: area.map_or(MAX_DIMENSIONS, |x| x.0),
+            }],
+            gutter,
         }
     }
 
+    /// The size of the area inside the border, if the page size is fixed
+    fn packing_area(&self) -> Option<(u32, u32)> { self.size.map(|x| self.gutter.inside(x)) }
+
+    /// How many times the edges of each texture are repeated around it
+    pub fn extrusion(&self) -> u32 { self.gutter.extrude }
+
     fn pack_rectangle(
         &mut self, dimensions: (u32, u32), settings: &PackingSettings,
     ) -> Option<PackingData> {
-        //create a copy of the rectangle to
-        //be packed, and apply spacing to it
-        let mut r = Rect::new(
-            0,
-            0,
-            dimensions.0 + settings.spacing,
-            dimensions.1 + settings.spacing,
-        );
-        //get the bounds of the set of packed rectangles
-        let bounds = self.packed_bounds();
-        //collect the indices of the free slots that can contain R
-        let mut candidates: Vec<usize> = (0..self.free_slots.len())
-            .filter(|&x| self.free_slots[x].can_contain(&r))
-            .collect();
-        //if rotation is allowed by the settings
-        if settings.rotation {
-            //collect the indices of free slots that
-            //can contain a rotated version of R
-            let r = Rect::new(
-                0,
-                0,
-                dimensions.1 + settings.spacing,
-                dimensions.0 + settings.spacing,
-            );
-            let extra: Vec<usize> = (0..self.free_slots.len())
-                .filter(|&x| self.free_slots[x].can_contain(&r) && !candidates.contains(&x))
-                .collect();
-            //and add them to the list of candidates
-            candidates.extend(extra);
-        }
-        //if there are no viable candidates
-        if candidates.is_empty() {
-            //see what the settings say about page size
-            match settings.page_size {
+        //create a copy of the rectangle to be packed,
+        //with the space it needs around it
+        let r = settings.footprint(dimensions);
+        //find the best position for R
+        let placement = match self.find_placement(&r, settings) {
+            Some(x) => x,
+            //if there are no viable candidates
+            //see what kind of page this is
+            None => match self.size {
                 //if the page size is fixed
                 Some(_) => {
                     //there is no way to pack R in this page
                     return None;
                 }
-                //if the page size is dynamic
-                None => {
-                    //create a new slot for R
+                //if the page size is dynamic, make room for R
+                None => self.grow(&r, settings)?,
+            },
+        };
+        Some(self.apply_placement(placement, dimensions, settings))
+    }
+
+    fn apply_placement(
+        &mut self, placement: Placement, dimensions: (u32, u32), settings: &PackingSettings,
+    ) -> PackingData {
+        //mark the space as occupied
+        match settings.algorithm {
+            PackingAlgorithm::MaxRects => self.place_in_slot(&placement),
+            PackingAlgorithm::Skyline(_) => self.place_on_skyline(&placement),
+            PackingAlgorithm::Guillotine(guillotine) => {
+                self.place_with_guillotine(&placement, guillotine)
+            }
+        }
+        //return the packing data for R, without the gutter
+        let dimensions = match placement.rotated {
+            true => (dimensions.1, dimensions.0),
+            false => dimensions,
+        };
+        PackingData {
+            position: self.gutter.within(&placement.rect, dimensions),
+            rotated: placement.rotated,
+        }
+    }
+
+    /// Marks the space around a texture at the given position (in page coordinates)
+    /// as occupied, if it's inside the page and no other texture is using it
+    fn reserve(&mut self, position: &Rect, settings: &PackingSettings) -> bool {
+        let offset = self.gutter.offset() + self.gutter.border;
+        if position.x < offset || position.y < offset {
+            return false;
+        }
+        let r = self.gutter.around(position);
+        let limits = self.packing_area().unwrap_or_else(|| {
+            self.gutter.inside(
+                settings
+                    .max_size
+                    .unwrap_or((MAX_DIMENSIONS, MAX_DIMENSIONS)),
+            )
+        });
+        let align = self.gutter.align;
+        let free = r.x.is_multiple_of(align)
+            && r.y.is_multiple_of(align)
+            && r.x + r.width <= limits.0
+            && r.y + r.height <= limits.1
+            && self
+                .packed_rects()
+                .into_iter()
+                .all(|x| self.gutter.around(x).intersection(&r).area() == 0);
+        if free {
+            self.occupy(&r, settings);
+        }
+        free
+    }
+
+    /// Takes the space R away from the free slots, or raises the skyline over it
+    fn occupy(&mut self, r: &Rect, settings: &PackingSettings) {
+        match settings.algorithm {
+            PackingAlgorithm::MaxRects => {
+                for idx in (0..self.free_slots.len()).rev() {
+                    if self.free_slots[idx].intersection(r).area() > 0 {
+                        let e = self.free_slots.remove(idx);
+                        self.free_slots.append(&mut e.slice_out(r));
+                    }
+                }
+                //drop the slots contained within others
+                let slots = mem::take(&mut self.free_slots);
+                for (a, slot) in slots.iter().enumerate() {
+                    let redundant = slots.iter().enumerate().any(|(b, other)| {
+                        a != b && other.contains(slot) && (other != slot || b < a)
+                    });
+                    if !redundant {
+                        self.free_slots.push(slot.clone());
+                    }
+                }
+            }
+            PackingAlgorithm::Guillotine(_) => {
+                //guillotine slots never overlap, so the ones around
+                //R are cut into pieces that don't overlap either
+                for idx in (0..self.free_slots.len()).rev() {
+                    let i = self.free_slots[idx].intersection(r);
+                    if i.area() == 0 {
+                        continue;
+                    }
+                    let s = self.free_slots.remove(idx);
+                    let (right, bottom) = (s.x + s.width, s.y + s.height);
+                    let pieces = [
+                        Rect::new(s.x, s.y, s.width, i.y - s.y),
+                        Rect::new(s.x, i.y + i.height, s.width, bottom - (i.y + i.height)),
+                        Rect::new(s.x, i.y, i.x - s.x, i.height),
+                        Rect::new(i.x + i.width, i.y, right - (i.x + i.width), i.height),
+                    ];
                     self.free_slots
-                        .push(if bounds.0 + r.width >= bounds.1 + r.height {
-                            Rect::new(0, bounds.1, cmp::max(bounds.0, r.width), r.height)
-                        } else {
-                            Rect::new(bounds.0, 0, r.width, cmp::max(bounds.1, r.height))
+                        .extend(pieces.into_iter().filter(|x| x.area() > 0));
+                }
+            }
+            PackingAlgorithm::Skyline(_) => {
+                //the skyline can't have holes, the space under R is lost
+                let (left, right, top) = (r.x, r.x + r.width, r.y + r.height);
+                let mut raised = Vec::new();
+                for s in self.skyline.iter() {
+                    let end = s.x + s.width;
+                    let parts = [
+                        (s.x, cmp::min(end, left), s.y),
+                        (
+                            cmp::max(s.x, left),
+                            cmp::min(end, right),
+                            cmp::max(s.y, top),
+                        ),
+                        (cmp::max(s.x, right), end, s.y),
+                    ];
+                    for (a, b, y) in parts.into_iter().filter(|x| x.0 < x.1) {
+                        raised.push(SkylineSegment {
+                            x: a,
+                            y,
+                            width: b - a,
                         });
-                    //and add it to the list of candidates
-                    candidates.push(self.free_slots.len() - 1);
+                    }
                 }
+                self.skyline = raised;
+                self.merge_skyline();
             }
         }
-        //at this point it's guaranteed that there is
-        //at least one viable slot for R in the set.
-        let dist_then_area = |a: &usize, b: &usize| {
-            let (a, b) = (&self.free_slots[*a], &self.free_slots[*b]);
-            Rect::cmp_by_distance(a, b).then(Rect::cmp_by_area(a, b))
-        };
-        let area_then_dist = |a: &usize, b: &usize| {
-            let (a, b) = (&self.free_slots[*a], &self.free_slots[*b]);
-            Rect::cmp_by_area(a, b).then(Rect::cmp_by_distance(a, b))
-        };
-        let x_then_y = |a: &usize, b: &usize| {
-            let (a, b) = (&self.free_slots[*a], &self.free_slots[*b]);
-            a.x.cmp(&b.x).then(a.y.cmp(&b.y))
-        };
-        let y_then_x = |a: &usize, b: &usize| {
-            let (a, b) = (&self.free_slots[*a], &self.free_slots[*b]);
-            a.y.cmp(&b.y).then(a.x.cmp(&b.x))
-        };
+    }
+
+    /// Dynamic pages only make room along their edges, so the gaps
+    /// between the reserved textures are turned into free slots
+    fn reclaim_gaps(&mut self, settings: &PackingSettings) {
+        if let PackingAlgorithm::Skyline(_) = settings.algorithm {
+            return;
+        }
+        let (w, h) = self.footprint_bounds();
+        self.free_slots = vec![Rect::new(0, 0, w, h)];
+        let footprints: Vec<Rect> = self
+            .packed_rects()
+            .into_iter()
+            .map(|x| self.gutter.around(x))
+            .collect();
+        for r in footprints.iter() {
+            self.occupy(r, settings);
+        }
+    }
+
+    fn find_placement(&self, r: &Rect, settings: &PackingSettings) -> Option<Placement> {
+        match settings.algorithm {
+            PackingAlgorithm::MaxRects | PackingAlgorithm::Guillotine(_) => {
+                self.find_slot(r, settings)
+            }
+            PackingAlgorithm::Skyline(method) => {
+                let limits = self.packing_area().unwrap_or_else(|| self.skyline_bounds());
+                self.find_skyline_position(r, limits, method, settings.rotation)
+            }
+        }
+    }
+
+    fn grow(&mut self, r: &Rect, settings: &PackingSettings) -> Option<Placement> {
+        //dynamic pages can't grow past the maximum size
+        let limits = self.gutter.inside(
+            settings
+                .max_size
+                .unwrap_or((MAX_DIMENSIONS, MAX_DIMENSIONS)),
+        );
+        match settings.algorithm {
+            PackingAlgorithm::MaxRects | PackingAlgorithm::Guillotine(_) => {
+                //get the bounds of the space taken up by the packed rectangles, and for
+                //guillotine pages the new slot must not overlap the free ones, so the
+                //whole area that was already cut up is avoided
+                let bounds = match settings.algorithm {
+                    PackingAlgorithm::MaxRects => self.footprint_bounds(),
+                    _ => self.occupied_bounds(),
+                };
+                let mut candidates = vec![(r.clone(), false)];
+                if settings.rotation {
+                    let mut rotated = r.clone();
+                    rotated.rotate();
+                    candidates.push((rotated, true));
+                }
+                //create a new slot for R, preferably in the direction that keeps the
+                //page squarer, and rotated only if nothing else fits the limits
+                let (slot, mut r, rotated) = candidates
+                    .into_iter()
+                    .flat_map(|(r, rotated)| {
+                        let down = Rect::new(0, bounds.1, cmp::max(bounds.0, r.width), r.height);
+                        let right = Rect::new(bounds.0, 0, r.width, cmp::max(bounds.1, r.height));
+                        let slots = match bounds.0 + r.width >= bounds.1 + r.height {
+                            true => [down, right],
+                            false => [right, down],
+                        };
+                        slots.map(|slot| (slot, r.clone(), rotated))
+                    })
+                    .find(|(slot, _, _)| {
+                        slot.x + slot.width <= limits.0 && slot.y + slot.height <= limits.1
+                    })?;
+                r.place_at(slot.x, slot.y);
+                self.free_slots.push(slot);
+                //and pick it
+                Some(Placement {
+                    slot: self.free_slots.len() - 1,
+                    rect: r,
+                    rotated,
+                    score: (0, 0),
+                })
+            }
+            PackingAlgorithm::Skyline(method) => {
+                //extend the skyline either downwards or to the right
+                let bounds = self.skyline_bounds();
+                let down = (cmp::min(cmp::max(bounds.0, r.width), limits.0), limits.1);
+                let right = (
+                    cmp::min(bounds.0 + r.width, limits.0),
+                    cmp::min(cmp::max(bounds.1, r.height), limits.1),
+                );
+                let directions = match bounds.0 + r.width >= bounds.1 + r.height {
+                    true => [down, right],
+                    false => [right, down],
+                };
+                directions.into_iter().find_map(|limits| {
+                    self.find_skyline_position(r, limits, method, settings.rotation)
+                })
+            }
+        }
+    }
+
+    fn find_slot(&self, r: &Rect, settings: &PackingSettings) -> Option<Placement> {
+        //collect the indices of the free slots that can contain R
+        let mut candidates: Vec<(usize, bool)> = (0..self.free_slots.len())
+            .filter(|&x| self.free_slots[x].can_contain(r))
+            .map(|x| (x, false))
+            .collect();
+        //if rotation is allowed by the settings
+        if settings.rotation {
+            //collect the indices of free slots that
+            //can contain a rotated version of R
+            let mut rotated = r.clone();
+            rotated.rotate();
+            candidates.extend(
+                (0..self.free_slots.len())
+                    .filter(|&x| self.free_slots[x].can_contain(&rotated))
+                    .map(|x| (x, true)),
+            );
+        }
         //pick the best candidate from the list
         //using the method defined in the settings
-        let pick = self.free_slots.remove(match settings.method {
-            PackingMethod::Distance => candidates.into_iter().min_by(dist_then_area).unwrap(),
-            PackingMethod::Area => candidates.into_iter().min_by(area_then_dist).unwrap(),
-            PackingMethod::Rows => candidates.into_iter().min_by(y_then_x).unwrap(),
-            PackingMethod::Columns => candidates.into_iter().min_by(x_then_y).unwrap(),
-        });
-        //if the picked rectangle can't contain R,
-        //it means R must be rotated to fit.
-        let rotated = if !pick.can_contain(&r) {
-            r.rotate();
-            true
-        } else {
-            false
-        };
-        //set R's position to the picked slot
-        r.place_at(pick.x, pick.y);
+        candidates
+            .into_iter()
+            .map(|(idx, rotated)| {
+                let slot = &self.free_slots[idx];
+                let mut r = r.clone();
+                if rotated {
+                    r.rotate();
+                }
+                r.place_at(slot.x, slot.y);
+                let score = self.score_slot(slot, &r, &settings.method);
+                Placement {
+                    slot: idx,
+                    rect: r,
+                    rotated,
+                    score,
+                }
+            })
+            .min_by_key(|x| x.score)
+    }
+
+    fn score_slot(&self, slot: &Rect, r: &Rect, method: &PackingMethod) -> (i64, i64) {
+        let distance = slot.squared_distance_from_origin() as i64;
+        let area = slot.area() as i64;
+        let leftover = (
+            slot.width.saturating_sub(r.width) as i64,
+            slot.height.saturating_sub(r.height) as i64,
+        );
+        match method {
+            PackingMethod::Distance => (distance, area),
+            PackingMethod::Area => (area, distance),
+            PackingMethod::ShortSideFit => (
+                cmp::min(leftover.0, leftover.1),
+                cmp::max(leftover.0, leftover.1),
+            ),
+            PackingMethod::LongSideFit => (
+                cmp::max(leftover.0, leftover.1),
+                cmp::min(leftover.0, leftover.1),
+            ),
+            PackingMethod::BottomLeft => ((slot.y + r.height) as i64, slot.x as i64),
+            PackingMethod::ContactPoint => {
+                let mut r = r.clone();
+                r.place_at(slot.x, slot.y);
+                (-(self.contact_length(&r) as i64), distance)
+            }
+            PackingMethod::Rows => (slot.y as i64, slot.x as i64),
+            PackingMethod::Columns => (slot.x as i64, slot.y as i64),
+        }
+    }
+
+    fn contact_length(&self, r: &Rect) -> u32 {
+        //the page borders count as contact
+        let mut contact = 0;
+        if r.x == 0 {
+            contact += r.height;
+        }
+        if r.y == 0 {
+            contact += r.width;
+        }
+        if let Some((w, h)) = self.packing_area() {
+            if r.x + r.width == w {
+                contact += r.height;
+            }
+            if r.y + r.height == h {
+                contact += r.width;
+            }
+        }
+        //and so do all the rectangles already packed
+        contact
+            + self
+                .packed_rects()
+                .into_iter()
+                .map(|x| self.gutter.around(x).contact_length(r))
+                .sum::<u32>()
+    }
+
+    fn place_in_slot(&mut self, placement: &Placement) {
+        let r = &placement.rect;
+        let pick = self.free_slots.remove(placement.slot);
         //destroy the picked rectangle, and add
         //its remains to the free slots list
-        self.free_slots.append(&mut pick.slice_out(&r));
+        self.free_slots.append(&mut pick.slice_out(r));
         //iterate over the remaining indices, back to front
         for idx in (0..self.free_slots.len()).rev() {
             //find the intersection between this rect and R
-            let overlap = self.free_slots[idx].intersection(&r);
+            let overlap = self.free_slots[idx].intersection(r);
             //if there is an intersection
             if overlap.area() > 0 {
                 //remove this rectangle from the free slots
                 let e = self.free_slots.remove(idx);
                 //and create new rectangles from the free space
-                self.free_slots.append(&mut e.slice_out(&r));
+                self.free_slots.append(&mut e.slice_out(r));
             }
         }
         //iterate one more time over the indices
@@ -389,25 +1415,219 @@ impl TexturePage {
                 }
             }
         }
-        //return the packing data for R
-        Some(PackingData {
-            position: Rect::new(
-                r.x,
-                r.y,
-                r.width - settings.spacing,
-                r.height - settings.spacing,
-            ),
-            rotated,
-        })
+    }
+
+    fn place_with_guillotine(&mut self, placement: &Placement, settings: GuillotineSettings) {
+        let r = &placement.rect;
+        let pick = self.free_slots.remove(placement.slot);
+        //leftover lengths on each axis
+        let (w, h) = (pick.width - r.width, pick.height - r.height);
+        //decide which way the remaining space is cut
+        let horizontal = match settings.split {
+            SplitRule::ShorterLeftoverAxis => w <= h,
+            SplitRule::LongerLeftoverAxis => w > h,
+            SplitRule::MinArea => (r.width as u64) * (h as u64) > (w as u64) * (r.height as u64),
+            SplitRule::MaxArea => (r.width as u64) * (h as u64) <= (w as u64) * (r.height as u64),
+        };
+        let (bottom, right) = if horizontal {
+            (
+                Rect::new(pick.x, r.y + r.height, pick.width, h),
+                Rect::new(r.x + r.width, pick.y, w, r.height),
+            )
+        } else {
+            (
+                Rect::new(pick.x, r.y + r.height, r.width, h),
+                Rect::new(r.x + r.width, pick.y, w, pick.height),
+            )
+        };
+        self.free_slots
+            .extend([bottom, right].into_iter().filter(|x| x.area() > 0));
+        if settings.merge {
+            self.merge_free_slots();
+        }
+    }
+
+    fn merge_free_slots(&mut self) {
+        //join pairs of free slots that share an entire edge
+        let mut a = 0;
+        while a < self.free_slots.len() {
+            let mut merged = false;
+            for b in (a + 1)..self.free_slots.len() {
+                let (ra, rb) = (&self.free_slots[a], &self.free_slots[b]);
+                let joined = if ra.x == rb.x && ra.width == rb.width {
+                    if ra.y + ra.height == rb.y {
+                        Some(Rect::new(ra.x, ra.y, ra.width, ra.height + rb.height))
+                    } else if rb.y + rb.height == ra.y {
+                        Some(Rect::new(ra.x, rb.y, ra.width, ra.height + rb.height))
+                    } else {
+                        None
+                    }
+                } else if ra.y == rb.y && ra.height == rb.height {
+                    if ra.x + ra.width == rb.x {
+                        Some(Rect::new(ra.x, ra.y, ra.width + rb.width, ra.height))
+                    } else if rb.x + rb.width == ra.x {
+                        Some(Rect::new(rb.x, ra.y, ra.width + rb.width, ra.height))
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                };
+                if let Some(joined) = joined {
+                    self.free_slots[a] = joined;
+                    self.free_slots.remove(b);
+                    merged = true;
+                    break;
+                }
+            }
+            //a merged slot may now match another one
+            if !merged {
+                a += 1;
+            }
+        }
+    }
+
+    fn find_skyline_position(
+        &self, r: &Rect, limits: (u32, u32), method: SkylineMethod, rotation: bool,
+    ) -> Option<Placement> {
+        let mut orientations = vec![(r.clone(), false)];
+        if rotation {
+            let mut r = r.clone();
+            r.rotate();
+            orientations.push((r, true));
+        }
+        let mut best: Option<Placement> = None;
+        for (r, rotated) in orientations {
+            for idx in 0..self.skyline.len() {
+                //find out how high R would sit if placed at this segment
+                let Some((y, waste)) = self.skyline_fit(idx, &r, limits) else {
+                    continue;
+                };
+                let x = self.skyline[idx].x;
+                let top = (y + r.height) as i64;
+                let score = match method {
+                    SkylineMethod::BottomLeft => (top, x as i64),
+                    SkylineMethod::MinWaste => (waste as i64, top),
+                };
+                if best.as_ref().is_none_or(|b| score < b.score) {
+                    best = Some(Placement {
+                        slot: idx,
+                        rect: Rect::new(x, y, r.width, r.height),
+                        rotated,
+                        score,
+                    });
+                }
+            }
+        }
+        best
+    }
+
+    fn skyline_fit(&self, idx: usize, r: &Rect, limits: (u32, u32)) -> Option<(u32, u64)> {
+        let x = self.skyline[idx].x;
+        if x + r.width > limits.0 {
+            return None;
+        }
+        //R rests on the highest segment below it
+        let right = x + r.width;
+        let spanned = self.skyline[idx..]
+            .iter()
+            .take_while(|s| s.x < right)
+            .collect::<Vec<_>>();
+        let y = spanned.iter().map(|s| s.y).max()?;
+        if y + r.height > limits.1 {
+            return None;
+        }
+        //and every gap between it and the lower segments is wasted
+        let waste = spanned
+            .iter()
+            .map(|s| ((y - s.y) as u64) * ((cmp::min(s.x + s.width, right) - s.x) as u64))
+            .sum();
+        Some((y, waste))
+    }
+
+    fn place_on_skyline(&mut self, placement: &Placement) {
+        let r = &placement.rect;
+        let right = r.x + r.width;
+        //the new segment starts exactly where the picked one did
+        self.skyline.insert(
+            placement.slot,
+            SkylineSegment {
+                x: r.x,
+                y: r.y + r.height,
+                width: r.width,
+            },
+        );
+        //shrink or remove the segments now covered by R
+        let idx = placement.slot + 1;
+        while idx < self.skyline.len() && self.skyline[idx].x < right {
+            let end = self.skyline[idx].x + self.skyline[idx].width;
+            if end <= right {
+                self.skyline.remove(idx);
+            } else {
+                self.skyline[idx].x = right;
+                self.skyline[idx].width = end - right;
+            }
+        }
+        self.merge_skyline();
+    }
+
+    /// Merges neighbouring segments at the same height
+    fn merge_skyline(&mut self) {
+        let mut idx = 0;
+        while idx + 1 < self.skyline.len() {
+            if self.skyline[idx].y == self.skyline[idx + 1].y {
+                self.skyline[idx].width += self.skyline[idx + 1].width;
+                self.skyline.remove(idx + 1);
+            } else {
+                idx += 1;
+            }
+        }
+    }
+
+    fn footprint_bounds(&self) -> (u32, u32) {
+        self.packed_rects()
+            .into_iter()
+            .map(|r| self.gutter.around(r))
+            .fold((0, 0), |(w, h), r| {
+                (cmp::max(w, r.x + r.width), cmp::max(h, r.y + r.height))
+            })
+    }
+
+    fn occupied_bounds(&self) -> (u32, u32) {
+        self.free_slots
+            .iter()
+            .fold(self.footprint_bounds(), |(w, h), r| {
+                (cmp::max(w, r.x + r.width), cmp::max(h, r.y + r.height))
+            })
+    }
+
+    fn skyline_bounds(&self) -> (u32, u32) {
+        self.skyline
+            .iter()
+            .filter(|s| s.y > 0)
+            .fold((0, 0), |(w, h), s| {
+                (cmp::max(w, s.x + s.width), cmp::max(h, s.y))
+            })
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self.size {
+            Some((w, h)) => (w, h),
+            None => self.packed_bounds(),
+        }
     }
 
     pub fn packed_bounds(&self) -> (u32, u32) {
+        //the extruded edges, the padding and the border are part
+        //of the image, the spacing after the last texture isn't
+        let margin = self.gutter.extrude + self.gutter.padding + self.gutter.border;
         let (mut w, mut h) = (0, 0);
         for r in self.packed_rects().iter() {
-            w = cmp::max(r.x.saturating_add(r.width), w);
-            h = cmp::max(r.y.saturating_add(r.height), h);
+            w = cmp::max(r.x.saturating_add(r.width + margin), w);
+            h = cmp::max(r.y.saturating_add(r.height + margin), h);
         }
-        (w, h)
+        let align = self.gutter.align;
+        (w.next_multiple_of(align), h.next_multiple_of(align))
     }
 
     fn packed_rects(&self) -> Vec<&Rect> {
@@ -424,53 +1644,167 @@ pub fn generate_arrange_settings(
 ) -> GeneralResult<PackingSettings> {
     match read_dimensions(&args.layout) {
         None => Err(format!("failed to read layout from '{}'", args.layout).into()),
-        Some((w, h)) => {
+        Some(layout) => {
             use interface::*;
-            Ok(PackingSettings {
-                method: match args.direction {
-                    Some(ArrangeDirection::Vertical) => PackingMethod::Columns,
-                    _ => PackingMethod::Rows,
-                },
-                spacing: 0,
-                rotation: false,
-                page_size: None,
-                source_treatment: Some(SourceTreatment {
-                    sorting: SortingMethod::LongSide,
-                    deduplicate: false,
-                }),
-                arrange: Some(ArrangeSettings { layout: (w, h) }),
-            })
+            let method = match args.direction {
+                Some(ArrangeDirection::Vertical) => PackingMethod::Columns,
+                _ => PackingMethod::Rows,
+            };
+            Ok(generate_layout_settings(layout, method))
         }
     }
 }
 
+/// Settings to arrange textures in a grid with the given number of columns and rows
+pub fn generate_layout_settings(layout: (u32, u32), method: PackingMethod) -> PackingSettings {
+    PackingSettings {
+        algorithm: PackingAlgorithm::MaxRects,
+        method,
+        spacing: 0,
+        extrude: 0,
+        padding: 0,
+        border: 0,
+        align: 1,
+        rotation: false,
+        global: false,
+        power_of_two: false,
+        page_size: None,
+        shrink_last_page: false,
+        search: None,
+        optimize_size: None,
+        max_size: None,
+        source_treatment: Some(SourceTreatment {
+            sorting: SortingMethod::LongSide,
+            deduplicate: false,
+            deduplicate_flips: false,
+            trim: None,
+        }),
+        arrange: Some(ArrangeSettings { layout }),
+    }
+}
+
 pub fn generate_packing_settings(
     args: &interface::PackArguments,
 ) -> GeneralResult<PackingSettings> {
+    let align = match args.align {
+        Some(0) => return Err("alignment must be at least 1 pixel.".into()),
+        Some(n) if args.power_of_two && !n.is_power_of_two() => {
+            return Err(format!("alignment {n} is not a power of two (see --po2).").into());
+        }
+        Some(n) => cmp::min(n, MAX_SPACING),
+        None => 1,
+    };
     let mut page_size = None;
     if let Some(size) = &args.page_size {
         match read_dimensions(size) {
             Some((w, h)) => {
-                if w <= MAX_DIMENSIONS && h <= MAX_DIMENSIONS {
-                    page_size = Some((w, h))
-                } else {
+                if w > MAX_DIMENSIONS || h > MAX_DIMENSIONS {
                     return Err(format!(
                         "largest supported page size is {MAX_DIMENSIONS}x{MAX_DIMENSIONS}"
                     )
                     .into());
+                } else if args.power_of_two && !(w.is_power_of_two() && h.is_power_of_two()) {
+                    return Err(
+                        format!("page size {w}x{h} is not a power of two (see --po2).").into(),
+                    );
+                } else if w % align != 0 || h % align != 0 {
+                    return Err(format!(
+                        "page size {w}x{h} is not a multiple of {align} (see --align)."
+                    )
+                    .into());
+                } else {
+                    page_size = Some((w, h))
                 }
             }
             None => return Err(format!("failed to read page size from '{size}'.").into()),
         }
     }
+    let budget = match args.search {
+        Some(seconds) if seconds > 0.0 => match Duration::try_from_secs_f64(seconds) {
+            Ok(budget) => Some(budget),
+            Err(_) => return Err(format!("invalid search time '{seconds}'.").into()),
+        },
+        Some(seconds) => return Err(format!("invalid search time '{seconds}'.").into()),
+        None => None,
+    };
+    let search = match (budget, args.search_iterations) {
+        (_, Some(0)) => return Err("the number of search iterations must be at least 1.".into()),
+        (None, None) => None,
+        (budget, iterations) => Some(SearchSettings {
+            budget,
+            iterations,
+            seed: args.seed.unwrap_or(0),
+        }),
+    };
+    let max_size = match &args.max_size {
+        Some(size) => match read_dimensions(size) {
+            Some((w, h)) if w > MAX_DIMENSIONS || h > MAX_DIMENSIONS => {
+                return Err(format!(
+                    "largest supported page size is {MAX_DIMENSIONS}x{MAX_DIMENSIONS}"
+                )
+                .into());
+            }
+            Some((w, h)) if args.power_of_two && !(w.is_power_of_two() && h.is_power_of_two()) => {
+                return Err(format!(
+                    "maximum page size {w}x{h} is not a power of two (see --po2)."
+                )
+                .into());
+            }
+            Some((w, h)) if w % align != 0 || h % align != 0 => {
+                return Err(format!(
+                    "maximum page size {w}x{h} is not a multiple of {align} (see --align)."
+                )
+                .into());
+            }
+            Some((w, h)) if w > 0 && h > 0 => Some((w, h)),
+            _ => return Err(format!("failed to read maximum page size from '{size}'.").into()),
+        },
+        None => None,
+    };
     Ok(PackingSettings {
-        method: match args.pack_by_area {
-            true => PackingMethod::Area,
-            false => PackingMethod::Distance,
+        algorithm: match args.engine {
+            Some(interface::PackingEngine::Skyline) => {
+                PackingAlgorithm::Skyline(match args.skyline_fit {
+                    Some(interface::SkylineFit::MinWaste) => SkylineMethod::MinWaste,
+                    _ => SkylineMethod::BottomLeft,
+                })
+            }
+            Some(interface::PackingEngine::Guillotine) => {
+                use interface::GuillotineSplit;
+                PackingAlgorithm::Guillotine(GuillotineSettings {
+                    split: match args.split {
+                        None | Some(GuillotineSplit::ShorterAxis) => SplitRule::ShorterLeftoverAxis,
+                        Some(GuillotineSplit::LongerAxis) => SplitRule::LongerLeftoverAxis,
+                        Some(GuillotineSplit::MinArea) => SplitRule::MinArea,
+                        Some(GuillotineSplit::MaxArea) => SplitRule::MaxArea,
+                    },
+                    merge: args.merge,
+                })
+            }
+            _ => PackingAlgorithm::MaxRects,
+        },
+        method: match (args.method, args.pack_by_area) {
+            (Some(method), _) => PackingMethod::from(method),
+            (None, true) => PackingMethod::Area,
+            (None, false) => PackingMethod::Distance,
         },
         spacing: cmp::min(args.spacing.unwrap_or(0), MAX_SPACING),
+        extrude: cmp::min(args.extrude.unwrap_or(0), MAX_SPACING),
+        padding: cmp::min(args.padding.unwrap_or(0), MAX_SPACING),
+        border: cmp::min(args.border.unwrap_or(0), MAX_SPACING),
+        align,
         rotation: args.rotate,
+        global: args.global_best_fit,
+        power_of_two: args.power_of_two,
         page_size,
+        shrink_last_page: args.shrink_last_page,
+        search,
+        optimize_size: match (args.optimize_size, args.square) {
+            (true, true) => Some(PageShape::Square),
+            (true, false) => Some(PageShape::Any),
+            (false, _) => None,
+        },
+        max_size,
         arrange: None,
         source_treatment: Some(SourceTreatment {
             sorting: match args.short_side_sort {
@@ -478,11 +1812,28 @@ pub fn generate_packing_settings(
                 false => SortingMethod::LongSide,
             },
             deduplicate: !args.include_duplicates,
+            deduplicate_flips: args.dedup_flips,
+            trim: match args.trim {
+                true => Some(args.trim_threshold.unwrap_or(0)),
+                false => None,
+            },
         }),
     })
 }
 
-fn read_dimensions(val: &str) -> Option<(u32, u32)> {
+/// Lower is better: total area first, then how much of the last page is used
+fn search_cost(packer: &TexturePacker) -> (u64, u64) {
+    let last = match packer.pages.last() {
+        Some(page) => {
+            let (w, h) = page.packed_bounds();
+            (w as u64) * (h as u64)
+        }
+        None => 0,
+    };
+    (packer.total_packed_area(), last)
+}
+
+pub fn read_dimensions(val: &str) -> Option<(u32, u32)> {
     if let Some((w, h)) = val.split_once('x') {
         if let (Ok(w), Ok(h)) = (w.parse::<u32>(), h.parse::<u32>()) {
             Some((w, h))
@@ -1,4 +1,4 @@
-use std::cmp::{self, Ordering};
+use std::cmp;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Rect {
@@ -22,17 +22,6 @@ impl Rect {
         }
     }
 
-    pub fn cmp_by_distance<T: AsRef<Rect>>(a: T, b: T) -> Ordering {
-        let (a, b) = (a.as_ref(), b.as_ref());
-        let (da, db) = (a.distance_from_origin(), b.distance_from_origin());
-        da.partial_cmp(&db).unwrap_or(Ordering::Equal)
-    }
-
-    pub fn cmp_by_area<T: AsRef<Rect>>(a: T, b: T) -> Ordering {
-        let (a, b) = (a.as_ref(), b.as_ref());
-        a.area().cmp(&b.area())
-    }
-
     pub fn place_at(&mut self, x: u32, y: u32) {
         self.x = x;
         self.y = y;
@@ -90,8 +79,25 @@ impl Rect {
             || r.y + r.height > self.y.saturating_add(self.height))
     }
 
-    pub fn distance_from_origin(&self) -> f64 {
-        ((self.x.pow(2).saturating_add(self.y.pow(2))) as f64).sqrt()
+    pub fn squared_distance_from_origin(&self) -> u64 {
+        (self.x as u64).pow(2) + (self.y as u64).pow(2)
+    }
+
+    pub fn contact_length(&self, r: &Rect) -> u32 {
+        let shared = |a: u32, a_len: u32, b: u32, b_len: u32| {
+            cmp::min(a.saturating_add(a_len), b.saturating_add(b_len))
+                .saturating_sub(cmp::max(a, b))
+        };
+        let mut length = 0;
+        //left or right edges touching
+        if self.x.saturating_add(self.width) == r.x || r.x.saturating_add(r.width) == self.x {
+            length += shared(self.y, self.height, r.y, r.height);
+        }
+        //top or bottom edges touching
+        if self.y.saturating_add(self.height) == r.y || r.y.saturating_add(r.height) == self.y {
+            length += shared(self.x, self.width, r.x, r.width);
+        }
+        length
     }
 
     pub fn area(&self) -> u32 { self.width.saturating_mul(self.height) }
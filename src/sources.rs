@@ -1,20 +1,66 @@
 use std::cmp;
 use std::collections::HashMap;
 use std::ffi::OsStr;
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::{Path, PathBuf};
 
 use crate::rectangle::Rect;
 use crate::utils;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SourceTexture {
     pub name: String,
     pub path: PathBuf,
     pub dimensions: Rect,
     pub replica_of: Option<String>,
     pub packing: Option<PackingData>,
+    pub trim: Option<TrimData>,
+    pub flip: Option<FlipData>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrimData {
+    /// The part of the source image that was kept
+    pub rect: Rect,
+    /// Dimensions of the whole source image
+    pub source_size: (u32, u32),
+}
+
+/// How a replica is transformed from its original: the x and y axes are swapped
+/// first (a diagonal flip), and then it's flipped horizontally and vertically
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FlipData {
+    pub horizontal: bool,
+    pub vertical: bool,
+    pub diagonal: bool,
+}
+
+impl FlipData {
+    /// Every combination of flips, starting with no flips at all. these
+    /// include the rotations by 90, 180 and 270 degrees
+    pub fn all() -> impl Iterator<Item = FlipData> {
+        (0..8).map(|i| FlipData {
+            horizontal: i & 1 != 0,
+            vertical: i & 2 != 0,
+            diagonal: i & 4 != 0,
+        })
+    }
+
+    pub fn apply(&self, image: &image::RgbaImage) -> image::RgbaImage {
+        let mut r = match self.diagonal {
+            true => image::RgbaImage::from_fn(image.height(), image.width(), |x, y| {
+                *image.get_pixel(y, x)
+            }),
+            false => image.clone(),
+        };
+        if self.horizontal {
+            image::imageops::flip_horizontal_in_place(&mut r);
+        }
+        if self.vertical {
+            image::imageops::flip_vertical_in_place(&mut r);
+        }
+        r
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -108,42 +154,89 @@ fn read_texture_info<P: AsRef<Path>>(source: P) -> utils::GeneralResult<SourceTe
         dimensions: Rect::new(0, 0, width, height),
         replica_of: None,
         packing: None,
+        trim: None,
+        flip: None,
     })
 }
 
-fn textures_are_duplicates(a: &SourceTexture, b: &SourceTexture) -> utils::GeneralResult<bool> {
-    //step 1: dimensions
-    if a.dimensions.width != b.dimensions.width || a.dimensions.height != b.dimensions.height {
-        return Ok(false);
-    }
-    //step 2: byte lengths
-    let (len_a, len_b) = (
-        std::fs::metadata(&a.path)?.len(),
-        std::fs::metadata(&b.path)?.len(),
-    );
-    if len_a != len_b {
-        return Ok(false);
-    }
-    //step 3, byte by byte comparison
-    const BUFFER_SIZE: usize = 1024;
-    let mut buffers = (vec![0u8; BUFFER_SIZE], vec![0u8; BUFFER_SIZE]);
-    let mut handles = (
-        BufReader::new(File::open(&a.path)?),
-        BufReader::new(File::open(&b.path)?),
-    );
-    loop {
-        let read = (
-            handles.0.read(&mut buffers.0)?,
-            handles.1.read(&mut buffers.1)?,
-        );
-        if read.0 == 0 && read.1 == 0 {
-            //EOF was reached and no difference was found, they are duplicates
-            return Ok(true);
-        } else if read.0 != read.1 || buffers.0 != buffers.1 {
-            //a difference was found, they're not duplicates
-            return Ok(false);
+pub fn trim_textures(sources: &mut [SourceTexture], threshold: u8) -> utils::GeneralResult<()> {
+    for src in sources.iter_mut() {
+        let image = image::open(&src.path)?.into_rgba8();
+        let (width, height) = image.dimensions();
+        //find the bounds of the pixels that are opaque enough
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if pixel.0[3] > threshold {
+                bounds = Some(match bounds {
+                    Some((x0, y0, x1, y1)) => (
+                        cmp::min(x0, x),
+                        cmp::min(y0, y),
+                        cmp::max(x1, x),
+                        cmp::max(y1, y),
+                    ),
+                    None => (x, y, x, y),
+                });
+            }
+        }
+        //images with nothing left are reduced to a single pixel
+        let rect = match bounds {
+            Some((x0, y0, x1, y1)) => Rect::new(x0, y0, x1 - x0 + 1, y1 - y0 + 1),
+            None => Rect::new(0, 0, 1, 1),
+        };
+        if rect.width != width || rect.height != height {
+            src.dimensions = Rect::new(0, 0, rect.width, rect.height);
+            src.trim = Some(TrimData {
+                rect,
+                source_size: (width, height),
+            });
         }
     }
+    Ok(())
+}
+
+/// The pixels of a source image that end up in the atlas, without its trimmed borders
+fn packed_pixels(src: &SourceTexture) -> utils::GeneralResult<image::RgbaImage> {
+    let image = image::open(&src.path)?.into_rgba8();
+    Ok(match src.trim.as_ref() {
+        Some(trim) => {
+            let r = &trim.rect;
+            image::imageops::crop_imm(&image, r.x, r.y, r.width, r.height).to_image()
+        }
+        None => image,
+    })
+}
+
+/// A hash of the decoded pixels of an image, and of its dimensions
+fn pixel_hash(image: &image::RgbaImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image.dimensions().hash(&mut hasher);
+    image.as_raw().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A hash that's the same for every flipped copy of an image
+pub fn flipped_hash(image: &image::RgbaImage, flips: &[FlipData]) -> u64 {
+    //flipped copies of an image all have the same set of hashes, the smallest one is used
+    flips
+        .iter()
+        .map(|x| pixel_hash(&x.apply(image)))
+        .min()
+        .unwrap_or_default()
+}
+
+/// Finds a candidate that turns into the image with one of the flips, and
+/// returns its key along with that flip
+pub fn find_flipped<'a, K: Copy>(
+    image: &image::RgbaImage, candidates: impl IntoIterator<Item = (K, &'a image::RgbaImage)>,
+    flips: &[FlipData],
+) -> Option<(K, FlipData)> {
+    //candidates are compared pixel by pixel, in case of hash collisions
+    candidates.into_iter().find_map(|(key, pixels)| {
+        flips
+            .iter()
+            .find(|x| x.apply(pixels) == *image)
+            .map(|x| (key, *x))
+    })
 }
 
 pub fn solve_name_collisions(sources: &mut [SourceTexture]) {
@@ -179,28 +272,55 @@ pub fn solve_name_collisions(sources: &mut [SourceTexture]) {
     }
 }
 
-pub fn deduplicate_textures(sources: &mut [SourceTexture]) -> utils::GeneralResult<()> {
-    //create a hashmap to check for images with the exact same dimensions
+pub fn deduplicate_textures(
+    sources: &mut [SourceTexture], include_flips: bool,
+) -> utils::GeneralResult<()> {
+    //the ways a replica can be transformed from its original
+    let flips: Vec<FlipData> = match include_flips {
+        true => FlipData::all().collect(),
+        false => vec![FlipData::default()],
+    };
+    //create a hashmap to check for images with the exact same dimensions,
+    //in any order if replicas can be flipped diagonally
     let mut sizes = HashMap::<(u32, u32), Vec<usize>>::new();
     //iterate over the sources, and group the indices using the image dimensions
     for (idx, src) in sources.iter().enumerate() {
+        let (w, h) = (src.dimensions.width, src.dimensions.height);
+        let size = match include_flips {
+            true => (cmp::min(w, h), cmp::max(w, h)),
+            false => (w, h),
+        };
         sizes
-            .entry((src.dimensions.width, src.dimensions.height))
+            .entry(size)
             .and_modify(|x| x.push(idx))
             .or_insert(vec![idx]);
     }
-    for group in sizes
-        .into_iter()
-        .filter_map(|x| if x.1.len() > 1 { Some(x.1) } else { None })
-    {
-        for (idx, first) in group.iter().enumerate() {
-            if sources[*first].replica_of.is_some() {
-                continue;
-            }
-            for second in group.iter().skip(idx + 1) {
-                if textures_are_duplicates(&sources[*first], &sources[*second])? {
-                    sources[*second].replica_of = Some(sources[*first].name.clone())
+    //only images that share their dimensions with another can be duplicates,
+    //group those again using a hash of their pixels. trimmed images are compared
+    //without their borders, so each replica keeps its own trim data
+    let mut hashes = HashMap::<u64, Vec<usize>>::new();
+    for group in sizes.into_values().filter(|x| x.len() > 1) {
+        for idx in group {
+            let image = packed_pixels(&sources[idx])?;
+            hashes
+                .entry(flipped_hash(&image, &flips))
+                .or_default()
+                .push(idx);
+        }
+    }
+    for mut group in hashes.into_values().filter(|x| x.len() > 1) {
+        //the first image in the list is the original
+        group.sort();
+        let mut originals: Vec<(usize, image::RgbaImage)> = Vec::new();
+        for idx in group {
+            let image = packed_pixels(&sources[idx])?;
+            let found = find_flipped(&image, originals.iter().map(|(i, x)| (*i, x)), &flips);
+            match found {
+                Some((original, flip)) => {
+                    sources[idx].replica_of = Some(sources[original].name.clone());
+                    sources[idx].flip = (flip != FlipData::default()).then_some(flip);
                 }
+                None => originals.push((idx, image)),
             }
         }
     }
@@ -208,16 +328,16 @@ pub fn deduplicate_textures(sources: &mut [SourceTexture]) -> utils::GeneralResu
 }
 
 pub fn validate_dimensions(
-    sources: &[SourceTexture], page_size: (u32, u32), spacing: u32,
+    sources: &[SourceTexture], page_size: (u32, u32),
+    minimum_page_size: impl Fn((u32, u32)) -> (u32, u32),
 ) -> utils::GeneralResult<()> {
     //build a collection of images that don't fit the provided page size
     let misfits: Vec<usize> = sources
         .iter()
         .enumerate()
         .filter_map(|(i, x)| {
-            if x.dimensions.width + spacing > page_size.0
-                || x.dimensions.height + spacing > page_size.1
-            {
+            let (w, h) = minimum_page_size((x.dimensions.width, x.dimensions.height));
+            if w > page_size.0 || h > page_size.1 {
                 Some(i)
             } else {
                 None
@@ -231,13 +351,7 @@ pub fn validate_dimensions(
         //otherwise, build an error message to return
         let mut err = String::new();
         err.push_str("the following images can't be packed with the current settings:");
-        err.push_str(
-            format!(
-                "\n\tpage size: {}x{}, spacing: {}px",
-                page_size.0, page_size.1, spacing
-            )
-            .as_str(),
-        );
+        err.push_str(format!("\n\tpage size: {}x{}", page_size.0, page_size.1).as_str());
         misfits.iter().for_each(|n| {
             let s = &sources[*n];
             let (name, w, h) = (&s.name, s.dimensions.width, s.dimensions.height);
@@ -246,7 +360,7 @@ pub fn validate_dimensions(
         //sources.len() is always > 0, so it's safe to call unwrap() here
         let min = sources
             .iter()
-            .map(|x| (x.dimensions.width + spacing, x.dimensions.height + spacing))
+            .map(|x| minimum_page_size((x.dimensions.width, x.dimensions.height)))
             .reduce(|req, n| (cmp::max(req.0, n.0), cmp::max(req.1, n.1)))
             .unwrap_or((0, 0));
         err.push_str(
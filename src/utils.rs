@@ -1,4 +1,5 @@
 use std::{
+    cmp,
     fmt::Display,
     io::Write,
     path::{Path, PathBuf},
@@ -14,8 +15,12 @@ pub fn info_message<S: Write, T: Display>(sink: &mut Option<S>, msg: T) {
 }
 
 pub fn exit_with_error<S: Write, T: Display>(sink: &mut Option<S>, msg: T) -> ! {
-    if let Some(sink) = sink {
-        let _ = writeln!(sink, "[ERROR] {msg}");
+    //without a log, the error still goes to stderr so stdout stays clean
+    match sink {
+        Some(sink) => {
+            let _ = writeln!(sink, "[ERROR] {msg}");
+        }
+        None => eprintln!("[ERROR] {msg}"),
     }
     std::process::exit(1);
 }
@@ -33,3 +38,28 @@ pub fn append_to_filename<T: AsRef<Path>>(path: T, suffix: &str) -> PathBuf {
     }
     r
 }
+
+/// Small xorshift generator, so that randomized searches can be repeated
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        //the state can never be zero
+        Random((seed ^ 0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A random number in the 0..n range
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % cmp::max(n, 1) as u64) as usize
+    }
+
+    /// A random number in the 0..1 range
+    pub fn unit(&mut self) -> f64 { (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64 }
+}